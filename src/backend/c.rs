@@ -0,0 +1,123 @@
+use std::mem;
+use crate::vm::{
+    Cell,
+    EofPolicy,
+    Op,
+    Overflow,
+    Program,
+    VmConfig,
+    VmError,
+};
+
+// Translate BF source to a self-contained C program, see `emit`
+pub fn transpile<C: Cell>(code: &str, config: &VmConfig) -> Result<String, VmError> {
+    emit::<C>(&Program::compile_for(code, config)?, config)
+}
+
+// Generate a C program with the same behaviour as running `prog` on a `Vm<C>` with the given config. Errors are
+// reported on stderr with the same messages as `VmError` (without step counts) and exit status 1. Only fixed,
+// non-empty tapes with strict pointer checks are supported.
+pub fn emit<C: Cell>(prog: &Program, config: &VmConfig) -> Result<String, VmError> {
+    super::check_config(config, "C output", false)?;
+
+    let mut s = String::new();
+    s += "// Generated by fuckvm\n";
+    s += "#include <stdint.h>\n#include <stdio.h>\n#include <stdlib.h>\n\n";
+    s += &format!("typedef uint{}_t cell;\n", mem::size_of::<C>() * 8);
+    s += &format!("#define CELL_MAX ((long long)UINT{}_MAX)\n", mem::size_of::<C>() * 8);
+    s += &format!("#define TAPE_LEN {}LL\n\n", config.tape_len);
+    s += "static cell tape[TAPE_LEN];\n\n";
+    s += PRELUDE;
+
+    s += "static inline void add(long long p, long long n, int pos) {\n";
+    match config.overflow {
+        Overflow::Wrapping => s += "    (void)pos;\n    tape[p] += (cell)n;\n",
+        Overflow::Saturating => {
+            s += "    long long v = (long long)tape[p] + n;\n";
+            s += "    (void)pos;\n";
+            s += "    tape[p] = (cell)(v < 0 ? 0 : v > CELL_MAX ? CELL_MAX : v);\n";
+        },
+        Overflow::Trapping => {
+            s += "    long long v = (long long)tape[p] + n;\n";
+            s += "    if (v < 0 || v > CELL_MAX) {\n";
+            s += "        fflush(stdout);\n";
+            s += "        fprintf(stderr, \"cell %lld overflowed at offset %d\\n\", p, pos);\n";
+            s += "        exit(1);\n";
+            s += "    }\n";
+            s += "    tape[p] = (cell)v;\n";
+        },
+    }
+    s += "}\n\n";
+
+    s += "static inline void input(long long p, int pos) {\n";
+    s += "    int c = getchar();\n";
+    s += "    if (c != EOF) {\n        tape[p] = (cell)c;\n        return;\n    }\n";
+    match config.eof {
+        EofPolicy::Zero => s += "    (void)pos;\n    tape[p] = 0;\n",
+        EofPolicy::MinusOne => s += "    (void)pos;\n    tape[p] = (cell)-1;\n",
+        EofPolicy::Unchanged => s += "    (void)pos;\n",
+        EofPolicy::Error => s += "    fail(\"unexpected end of input\", pos);\n",
+    }
+    s += "}\n\n";
+
+    s += "int main(void) {\n    long long p = 0;\n";
+    let mut depth = 1;
+    for (idx, op) in prog.ops().iter().enumerate() {
+        let pos = prog.span(idx).unwrap().start;
+        let indent = "    ".repeat(depth);
+        match *op {
+            Op::Add(n) => s += &format!("{}add(p, {}, {});\n", indent, n, pos),
+            Op::Move(n) => s += &format!("{}p = move(p, {}, {});\n", indent, n, pos),
+            Op::Clear => s += &format!("{}tape[p] = 0;\n", indent),
+            Op::MulAdd { offset, factor } => s += &format!(
+                "{}if (tape[p]) add(move(p, {}, {}), (long long)tape[p] * {}, {});\n",
+                indent,
+                offset,
+                pos,
+                factor,
+                pos,
+            ),
+            Op::ScanLeft(stride) => s += &format!("{}while (tape[p]) p = move(p, -{}, {});\n", indent, stride, pos),
+            Op::ScanRight(stride) => s += &format!("{}while (tape[p]) p = move(p, {}, {});\n", indent, stride, pos),
+            Op::Out => s += &format!("{}putchar((unsigned char)tape[p]);\n", indent),
+            Op::In => s += &format!("{}input(p, {});\n", indent, pos),
+            Op::Open(_) => {
+                s += &format!("{}while (tape[p]) {{\n", indent);
+                depth += 1;
+            },
+            Op::Close(_) => {
+                depth -= 1;
+                s += &format!("{}}}\n", "    ".repeat(depth));
+            },
+            Op::Debug => s += &format!("{}debug(p, {});\n", indent, pos),
+        }
+    }
+    s += "    (void)p;\n    return 0;\n}\n";
+    Ok(s)
+}
+
+const PRELUDE: &str = r#"static void fail(const char *msg, int pos) {
+    fflush(stdout);
+    fprintf(stderr, "%s at offset %d\n", msg, pos);
+    exit(1);
+}
+
+static inline long long move(long long p, long long n, int pos) {
+    p += n;
+    if (p < 0) {
+        fail("tape pointer moved below the start of the tape", pos);
+    } else if (p >= TAPE_LEN) {
+        fail("tape pointer moved past the end of the tape", pos);
+    }
+    return p;
+}
+
+static inline void debug(long long p, int pos) {
+    fprintf(stderr, "[%d] ptr=%lld:", pos, p);
+    for (long long i = 0; i < 20 && i < TAPE_LEN; i++) {
+        fprintf(stderr, "%s %llu", i ? "," : "", (unsigned long long)tape[i]);
+    }
+    fprintf(stderr, "\n");
+}
+
+"#;
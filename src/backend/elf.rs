@@ -0,0 +1,235 @@
+use crate::vm::{
+    EofPolicy,
+    Program,
+    VmConfig,
+    VmError,
+};
+use super::x86::{
+    self,
+    Assembler,
+    Cond,
+    Label,
+    Reg,
+};
+
+// Memory layout
+// -------------
+// 0x400000     ELF and program headers, error messages, op offset table, code (read + execute)
+// 0x10000000   tape followed by a small buffer for formatting numbers (read + write, zeroed)
+
+const CODE_ADDR: u64 = 0x400000;
+const DATA_ADDR: u64 = 0x10000000;
+const HEADERS_LEN: usize = 64 + 2 * 56;
+const NUM_BUF_LEN: u64 = 32;
+
+const SYS_READ: u32 = 0;
+const SYS_WRITE: u32 = 1;
+const SYS_EXIT: u32 = 60;
+const EINTR: u32 = -4i32 as u32;
+
+const MSG_UNDERFLOW: &[u8] = b"tape pointer moved below the start of the tape at offset ";
+const MSG_OVERFLOW: &[u8] = b"tape pointer moved past the end of the tape at offset ";
+const MSG_EOF: &[u8] = b"unexpected end of input at offset ";
+const MSG_IO: &[u8] = b"I/O error\n";
+
+// Translate BF source to a static Linux x86-64 executable, see `emit`
+pub fn assemble(code: &str, config: &VmConfig) -> Result<Vec<u8>, VmError> {
+    emit(&Program::compile_for(code, config)?, config)
+}
+
+// Generate a static Linux x86-64 executable with the same behaviour as running `prog` on a `Vm` with the given
+// config, using only the `read`, `write` and `exit` syscalls. Errors are reported on stderr with the same messages
+// as `VmError` (without step counts) and exit status 1. Only fixed tapes of 8-bit wrapping cells with strict pointer
+// checks are supported, and debug characters are ignored.
+pub fn emit(prog: &Program, config: &VmConfig) -> Result<Vec<u8>, VmError> {
+    use Reg::*;
+
+    super::check_config(config, "executable output", true)?;
+    if DATA_ADDR + config.tape_len as u64 + NUM_BUF_LEN > i32::MAX as u64 {
+        return Err(VmError::Unsupported("tape is too long".to_string()));
+    }
+
+    // Read-only data goes straight after the headers so that its addresses are known while generating code
+    let mut data = Vec::new();
+    let mut add_data = |bytes: &[u8]| {
+        let addr = CODE_ADDR + (HEADERS_LEN + data.len()) as u64;
+        data.extend_from_slice(bytes);
+        addr as u32
+    };
+    let msg = |addr: u32, msg: &[u8]| (addr, msg.len() as u32);
+    let underflow = msg(add_data(MSG_UNDERFLOW), MSG_UNDERFLOW);
+    let overflow = msg(add_data(MSG_OVERFLOW), MSG_OVERFLOW);
+    let eof = msg(add_data(MSG_EOF), MSG_EOF);
+    let io_msg = msg(add_data(MSG_IO), MSG_IO);
+    let offsets = (0..prog.len())
+        .flat_map(|idx| (prog.span(idx).unwrap().start as u32).to_le_bytes())
+        .collect::<Vec<_>>();
+    let offset_table = add_data(&offsets);
+    let code_addr = CODE_ADDR + (HEADERS_LEN + data.len()) as u64;
+
+    let mut asm = Assembler::new();
+    let report = asm.new_label();
+    let ptr_error = asm.new_label();
+    let mut io = ElfIo {
+        eof: config.eof,
+        eof_error: asm.new_label(),
+        io_error: asm.new_label(),
+    };
+
+    asm.mov_imm32(Rbx, DATA_ADDR as u32);
+    asm.xor32(R12, R12);
+    asm.xor32(R13, R13);
+    asm.mov_imm64(R15, config.tape_len as u64);
+    x86::lower(prog, &mut asm, &mut io, ptr_error)
+        .map_err(|err| VmError::Unsupported(format!("op {} can't be encoded", err.0)))?;
+    exit(&mut asm, 0);
+
+    // rax holds the attempted pointer, rcx the op index
+    let below = asm.new_label();
+    asm.bind(ptr_error);
+    asm.mov(R14, Rcx);
+    asm.test(Rax, Rax);
+    asm.jcc(Cond::S, below);
+    message(&mut asm, overflow, report);
+    asm.bind(below);
+    message(&mut asm, underflow, report);
+
+    asm.bind(io.eof_error);
+    message(&mut asm, eof, report);
+
+    asm.bind(io.io_error);
+    asm.mov_imm32(Rsi, io_msg.0);
+    asm.mov_imm32(Rdx, io_msg.1);
+    write_stderr(&mut asm);
+    exit(&mut asm, 1);
+
+    // Print the message in rsi/rdx followed by the source offset of the op whose index is in r14
+    let buf_end = (DATA_ADDR + config.tape_len as u64 + NUM_BUF_LEN) as u32;
+    let digits = asm.new_label();
+    asm.bind(report);
+    write_stderr(&mut asm);
+    asm.load_table32(Rax, offset_table as i32, R14);
+    asm.mov_imm32(Rcx, 10);
+    asm.mov_imm32(Rsi, buf_end);
+    asm.dec(Rsi);
+    asm.store_byte_disp(Rsi, 0, Rcx);
+    asm.bind(digits);
+    asm.xor32(Rdx, Rdx);
+    asm.div32(Rcx);
+    asm.add_imm(Rdx, b'0' as i32);
+    asm.dec(Rsi);
+    asm.store_byte_disp(Rsi, 0, Rdx);
+    asm.test(Rax, Rax);
+    asm.jcc(Cond::Ne, digits);
+    asm.mov_imm32(Rdx, buf_end);
+    asm.sub(Rdx, Rsi);
+    write_stderr(&mut asm);
+    exit(&mut asm, 1);
+
+    let code = asm.finish();
+
+    let file_len = HEADERS_LEN + data.len() + code.len();
+    let mut elf = Vec::with_capacity(file_len);
+
+    // ELF header
+    elf.extend_from_slice(&[0x7F, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    elf.extend_from_slice(&2u16.to_le_bytes()); // Executable
+    elf.extend_from_slice(&0x3Eu16.to_le_bytes()); // x86-64
+    elf.extend_from_slice(&1u32.to_le_bytes());
+    elf.extend_from_slice(&code_addr.to_le_bytes());
+    elf.extend_from_slice(&64u64.to_le_bytes()); // Program headers
+    elf.extend_from_slice(&0u64.to_le_bytes()); // Section headers
+    elf.extend_from_slice(&0u32.to_le_bytes());
+    for half in [64u16, 56, 2, 64, 0, 0] {
+        elf.extend_from_slice(&half.to_le_bytes());
+    }
+
+    // Program headers
+    segment(&mut elf, 0x5, CODE_ADDR, file_len as u64, file_len as u64);
+    segment(&mut elf, 0x6, DATA_ADDR, 0, config.tape_len as u64 + NUM_BUF_LEN);
+
+    elf.extend_from_slice(&data);
+    elf.extend_from_slice(&code);
+    Ok(elf)
+}
+
+fn segment(elf: &mut Vec<u8>, flags: u32, addr: u64, file_len: u64, mem_len: u64) {
+    elf.extend_from_slice(&1u32.to_le_bytes()); // Loadable
+    elf.extend_from_slice(&flags.to_le_bytes());
+    for word in [0, addr, addr, file_len, mem_len, 0x1000] {
+        elf.extend_from_slice(&word.to_le_bytes());
+    }
+}
+
+fn exit(asm: &mut Assembler, status: u32) {
+    asm.mov_imm32(Reg::Rax, SYS_EXIT);
+    asm.mov_imm32(Reg::Rdi, status);
+    asm.syscall();
+}
+
+// Write rdx bytes from rsi to stderr
+fn write_stderr(asm: &mut Assembler) {
+    asm.mov_imm32(Reg::Rax, SYS_WRITE);
+    asm.mov_imm32(Reg::Rdi, 2);
+    asm.syscall();
+}
+
+fn message(asm: &mut Assembler, (addr, len): (u32, u32), report: Label) {
+    asm.mov_imm32(Reg::Rsi, addr);
+    asm.mov_imm32(Reg::Rdx, len);
+    asm.jmp(report);
+}
+
+struct ElfIo {
+    eof: EofPolicy,
+    // Entered with the op index in r14
+    eof_error: Label,
+    io_error: Label,
+}
+
+impl ElfIo {
+    // Transfer one byte between the current cell and a file descriptor, retrying if interrupted. Leaves the result
+    // of the syscall in rax.
+    fn syscall(&mut self, asm: &mut Assembler, num: u32, fd: u32) {
+        let retry = asm.new_label();
+        let done = asm.new_label();
+        asm.bind(retry);
+        asm.mov_imm32(Reg::Rax, num);
+        asm.mov_imm32(Reg::Rdi, fd);
+        asm.lea_index(Reg::Rsi, Reg::Rbx, Reg::R12);
+        asm.mov_imm32(Reg::Rdx, 1);
+        asm.syscall();
+        asm.test(Reg::Rax, Reg::Rax);
+        asm.jcc(Cond::Ns, done);
+        asm.cmp_imm32(Reg::Rax, EINTR);
+        asm.jcc(Cond::E, retry);
+        asm.jmp(self.io_error);
+        asm.bind(done);
+    }
+}
+
+impl x86::Io for ElfIo {
+    fn input(&mut self, asm: &mut Assembler, op: usize) {
+        let done = asm.new_label();
+        self.syscall(asm, SYS_READ, 0);
+        asm.test(Reg::Rax, Reg::Rax);
+        asm.jcc(Cond::Ne, done);
+        match self.eof {
+            EofPolicy::Zero => asm.store_byte_imm(Reg::Rbx, Reg::R12, 0),
+            EofPolicy::MinusOne => asm.store_byte_imm(Reg::Rbx, Reg::R12, 0xFF),
+            EofPolicy::Unchanged => {},
+            EofPolicy::Error => {
+                asm.mov_imm32(Reg::R14, op as u32);
+                asm.jmp(self.eof_error);
+            },
+        }
+        asm.bind(done);
+        asm.inc(Reg::R13);
+    }
+
+    fn output(&mut self, asm: &mut Assembler, _op: usize) {
+        self.syscall(asm, SYS_WRITE, 1);
+    }
+
+    fn debug(&mut self, _asm: &mut Assembler, _op: usize) {}
+}
@@ -0,0 +1,26 @@
+use crate::vm::{
+    Overflow,
+    TapeMode,
+    VmConfig,
+    VmError,
+};
+
+pub mod c;
+pub mod elf;
+pub mod wat;
+pub mod x86;
+
+// Compiled code needs a fixed, non-empty tape with strict pointer checks, and backends that only implement wrapping
+// arithmetic also need wrapping cells. `target` names the backend in the error.
+pub(crate) fn check_config(config: &VmConfig, target: &str, wrapping_only: bool) -> Result<(), VmError> {
+    let unsupported = |what: &str| Err(VmError::Unsupported(format!("{} requires {}", target, what)));
+    if config.tape_mode != TapeMode::Fixed || config.tape_len == 0 {
+        unsupported("a fixed, non-empty tape")
+    } else if config.lenient_pointer {
+        unsupported("strict pointer checks")
+    } else if wrapping_only && config.overflow != Overflow::Wrapping {
+        unsupported("wrapping cells")
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,237 @@
+use std::{
+    convert::TryFrom,
+    mem,
+};
+use crate::vm::{
+    Cell,
+    EofPolicy,
+    Op,
+    Program,
+    VmConfig,
+    VmError,
+};
+
+// Values returned by the exported `run` function
+pub const STATUS_HALTED: i32 = 0;
+pub const STATUS_UNDERFLOW: i32 = 1;
+pub const STATUS_OVERFLOW: i32 = 2;
+pub const STATUS_EOF: i32 = 3;
+
+const PAGE_SIZE: usize = 0x10000;
+
+// Translate BF source to a WebAssembly text module, see `emit`
+pub fn transpile<C: Cell>(code: &str, config: &VmConfig) -> Result<String, VmError> {
+    emit::<C>(&Program::compile_for(code, config)?, config)
+}
+
+// Generate a WebAssembly text module that runs `prog` like a `Vm<C>` with the given config. The tape lives at the
+// start of the exported linear memory, and the host provides `env.read_byte` (returning -1 at the end of input) and
+// `env.write_byte`. The exported `run` function returns one of the `STATUS_*` values, and `error_pos` gives the
+// source offset of the op that failed. Only fixed, non-empty tapes of wrapping cells with strict pointer checks are
+// supported, and debug characters are ignored.
+pub fn emit<C: Cell>(prog: &Program, config: &VmConfig) -> Result<String, VmError> {
+    super::check_config(config, "WebAssembly output", true)?;
+    if i32::try_from(config.tape_len * mem::size_of::<C>()).is_err() {
+        return Err(VmError::Unsupported("tape is too long".to_string()));
+    }
+
+    let mut f = Emitter {
+        s: String::new(),
+        depth: 2,
+        tape_len: config.tape_len,
+        width: mem::size_of::<C>(),
+    };
+
+    for (idx, op) in prog.ops().iter().enumerate() {
+        let pos = prog.span(idx).unwrap().start;
+        let imm = |n: isize| i32::try_from(n).map_err(|_| VmError::Unsupported(format!("op {} can't be encoded", idx)));
+        match *op {
+            Op::Add(n) => {
+                f.addr("$p");
+                f.load("$p");
+                f.line(&format!("i32.const {}", n));
+                f.line("i32.add");
+                f.store();
+            },
+            Op::Move(n) => {
+                f.offset_ptr(imm(n)?, pos);
+                f.line("local.get $t");
+                f.line("local.set $p");
+            },
+            Op::Clear => {
+                f.addr("$p");
+                f.line("i32.const 0");
+                f.store();
+            },
+            Op::MulAdd { offset, factor } => {
+                f.load("$p");
+                f.open("if");
+                f.offset_ptr(imm(offset)?, pos);
+                f.addr("$t");
+                f.load("$t");
+                f.load("$p");
+                f.line(&format!("i32.const {}", factor));
+                f.line("i32.mul");
+                f.line("i32.add");
+                f.store();
+                f.close();
+            },
+            Op::ScanLeft(stride) | Op::ScanRight(stride) => {
+                let stride = imm(stride as isize)?;
+                f.open("block");
+                f.open("loop");
+                f.load("$p");
+                f.line("i32.eqz");
+                f.line("br_if 1");
+                f.offset_ptr(if let Op::ScanLeft(_) = op { -stride } else { stride }, pos);
+                f.line("local.get $t");
+                f.line("local.set $p");
+                f.line("br 0");
+                f.close();
+                f.close();
+            },
+            Op::Out => {
+                f.load("$p");
+                f.line("i32.const 255");
+                f.line("i32.and");
+                f.line("call $write_byte");
+            },
+            Op::In => {
+                f.line("call $read_byte");
+                f.line("local.tee $t");
+                f.line("i32.const 0");
+                f.line("i32.lt_s");
+                f.open("if");
+                match config.eof {
+                    EofPolicy::Zero => {
+                        f.addr("$p");
+                        f.line("i32.const 0");
+                        f.store();
+                    },
+                    EofPolicy::MinusOne => {
+                        f.addr("$p");
+                        f.line("i32.const -1");
+                        f.store();
+                    },
+                    EofPolicy::Unchanged => f.line("nop"),
+                    EofPolicy::Error => {
+                        f.line(&format!("i32.const {}", STATUS_EOF));
+                        f.fail(pos);
+                    },
+                }
+                f.depth -= 1;
+                f.line("else");
+                f.depth += 1;
+                f.addr("$p");
+                f.line("local.get $t");
+                f.store();
+                f.close();
+            },
+            Op::Open(_) => {
+                f.open("block");
+                f.load("$p");
+                f.line("i32.eqz");
+                f.line("br_if 0");
+                f.open("loop");
+            },
+            Op::Close(_) => {
+                f.load("$p");
+                f.line("br_if 0");
+                f.close();
+                f.close();
+            },
+            Op::Debug => {},
+        }
+    }
+
+    let pages = (config.tape_len * f.width).div_ceil(PAGE_SIZE).max(1);
+    let mut s = String::new();
+    s += "(module\n";
+    s += "  (import \"env\" \"read_byte\" (func $read_byte (result i32)))\n";
+    s += "  (import \"env\" \"write_byte\" (func $write_byte (param i32)))\n";
+    s += &format!("  (memory (export \"memory\") {})\n", pages);
+    s += "  (global $error_pos (mut i32) (i32.const 0))\n";
+    s += "  (func (export \"error_pos\") (result i32)\n    global.get $error_pos\n  )\n";
+    s += "  (func (export \"run\") (result i32)\n";
+    s += "    (local $p i32)\n    (local $t i32)\n";
+    s += &f.s;
+    s += &format!("    i32.const {}\n  )\n)\n", STATUS_HALTED);
+    Ok(s)
+}
+
+struct Emitter {
+    s: String,
+    depth: usize,
+    tape_len: usize,
+    width: usize,
+}
+
+impl Emitter {
+    fn line(&mut self, instr: &str) {
+        self.s += &"  ".repeat(self.depth);
+        self.s += instr;
+        self.s += "\n";
+    }
+
+    fn open(&mut self, instr: &str) {
+        self.line(instr);
+        self.depth += 1;
+    }
+
+    fn close(&mut self) {
+        self.depth -= 1;
+        self.line("end");
+    }
+
+    // Push the address of the cell whose index is in `local`
+    fn addr(&mut self, local: &str) {
+        self.line(&format!("local.get {}", local));
+        if self.width > 1 {
+            self.line(&format!("i32.const {}", self.width.trailing_zeros()));
+            self.line("i32.shl");
+        }
+    }
+
+    fn load(&mut self, local: &str) {
+        self.addr(local);
+        self.line(match self.width {
+            1 => "i32.load8_u",
+            2 => "i32.load16_u",
+            _ => "i32.load",
+        });
+    }
+
+    fn store(&mut self) {
+        self.line(match self.width {
+            1 => "i32.store8",
+            2 => "i32.store16",
+            _ => "i32.store",
+        });
+    }
+
+    // Set `$t` to `$p + n`, returning early if it lies outside the tape
+    fn offset_ptr(&mut self, n: i32, pos: usize) {
+        self.line("local.get $p");
+        self.line(&format!("i32.const {}", n));
+        self.line("i32.add");
+        self.line("local.tee $t");
+        self.line(&format!("i32.const {}", self.tape_len));
+        self.line("i32.ge_u");
+        self.open("if");
+        // Negative pointers are underflows, the rest overflows
+        self.line(&format!("i32.const {}", STATUS_OVERFLOW));
+        self.line("local.get $t");
+        self.line("i32.const 31");
+        self.line("i32.shr_u");
+        self.line("i32.sub");
+        self.fail(pos);
+        self.close();
+    }
+
+    // Return the status on top of the stack, recording the source offset of the failed op
+    fn fail(&mut self, pos: usize) {
+        self.line(&format!("i32.const {}", pos));
+        self.line("global.set $error_pos");
+        self.line("return");
+    }
+}
@@ -0,0 +1,432 @@
+use std::convert::TryFrom;
+use crate::vm::{
+    Op,
+    Program,
+};
+
+// Register conventions for generated code
+// ---------------------------------------
+// rbx = tape base address
+// r12 = tape pointer (cell index)
+// r13 = steps executed
+// r14 = reserved for the target (e.g. a context pointer)
+// r15 = tape length
+// rax, rcx, rdx, rsi and rdi are scratch
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Reg {
+    Rax = 0,
+    Rcx = 1,
+    Rdx = 2,
+    Rbx = 3,
+    Rsp = 4,
+    Rbp = 5,
+    Rsi = 6,
+    Rdi = 7,
+    R8 = 8,
+    R9 = 9,
+    R10 = 10,
+    R11 = 11,
+    R12 = 12,
+    R13 = 13,
+    R14 = 14,
+    R15 = 15,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Cond {
+    B = 0x2,
+    Ae = 0x3,
+    E = 0x4,
+    Ne = 0x5,
+    Be = 0x6,
+    A = 0x7,
+    S = 0x8,
+    Ns = 0x9,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Label(usize);
+
+// A minimal x86-64 assembler covering the instructions needed to lower VM ops
+#[derive(Default)]
+pub struct Assembler {
+    code: Vec<u8>,
+    labels: Vec<Option<usize>>,
+    // Positions of rel32 operands that refer to labels
+    fixups: Vec<(usize, Label)>,
+}
+
+impl Assembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.code.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.code.is_empty()
+    }
+
+    pub fn new_label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(self.labels.len() - 1)
+    }
+
+    pub fn bind(&mut self, label: Label) {
+        self.labels[label.0] = Some(self.code.len());
+    }
+
+    // Resolve jumps and return the machine code. Panics if a label was used without being bound.
+    pub fn finish(mut self) -> Vec<u8> {
+        for (pos, label) in &self.fixups {
+            let target = self.labels[label.0].expect("unbound label");
+            let rel = target as i64 - (*pos as i64 + 4);
+            self.code[*pos..*pos + 4].copy_from_slice(&(rel as i32).to_le_bytes());
+        }
+        self.code
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    fn rex(&mut self, w: bool, r: u8, x: u8, b: u8, force: bool) {
+        let rex = 0x40 | (w as u8) << 3 | (r >> 3) << 2 | (x >> 3) << 1 | b >> 3;
+        if rex != 0x40 || force {
+            self.code.push(rex);
+        }
+    }
+
+    fn modrm_rr(&mut self, reg: u8, rm: u8) {
+        self.code.push(0xC0 | (reg & 7) << 3 | rm & 7);
+    }
+
+    // [base + disp32]
+    fn modrm_disp(&mut self, reg: u8, base: Reg, disp: i32) {
+        self.code.push(0x80 | (reg & 7) << 3 | base as u8 & 7);
+        if base as u8 & 7 == 4 {
+            self.code.push(0x24);
+        }
+        self.bytes(&disp.to_le_bytes());
+    }
+
+    // [base + index], base must not be rbp or r13
+    fn modrm_index(&mut self, reg: u8, base: Reg, index: Reg) {
+        self.code.push((reg & 7) << 3 | 0x4);
+        self.code.push((index as u8 & 7) << 3 | base as u8 & 7);
+    }
+
+    pub fn push(&mut self, reg: Reg) {
+        self.rex(false, 0, 0, reg as u8, false);
+        self.code.push(0x50 | reg as u8 & 7);
+    }
+
+    pub fn pop(&mut self, reg: Reg) {
+        self.rex(false, 0, 0, reg as u8, false);
+        self.code.push(0x58 | reg as u8 & 7);
+    }
+
+    pub fn ret(&mut self) {
+        self.code.push(0xC3);
+    }
+
+    pub fn syscall(&mut self) {
+        self.bytes(&[0x0F, 0x05]);
+    }
+
+    pub fn call(&mut self, reg: Reg) {
+        self.rex(false, 0, 0, reg as u8, false);
+        self.code.push(0xFF);
+        self.modrm_rr(2, reg as u8);
+    }
+
+    // mov dst, src (64-bit)
+    pub fn mov(&mut self, dst: Reg, src: Reg) {
+        self.rex(true, src as u8, 0, dst as u8, false);
+        self.code.push(0x89);
+        self.modrm_rr(src as u8, dst as u8);
+    }
+
+    // mov dst, imm32 (zero-extended to 64 bits)
+    pub fn mov_imm32(&mut self, dst: Reg, imm: u32) {
+        self.rex(false, 0, 0, dst as u8, false);
+        self.code.push(0xB8 | dst as u8 & 7);
+        self.bytes(&imm.to_le_bytes());
+    }
+
+    pub fn mov_imm64(&mut self, dst: Reg, imm: u64) {
+        self.rex(true, 0, 0, dst as u8, false);
+        self.code.push(0xB8 | dst as u8 & 7);
+        self.bytes(&imm.to_le_bytes());
+    }
+
+    // mov [base + disp], src (64-bit)
+    pub fn store(&mut self, base: Reg, disp: i32, src: Reg) {
+        self.rex(true, src as u8, 0, base as u8, false);
+        self.code.push(0x89);
+        self.modrm_disp(src as u8, base, disp);
+    }
+
+    // mov dst, [base + disp] (64-bit)
+    pub fn load(&mut self, dst: Reg, base: Reg, disp: i32) {
+        self.rex(true, dst as u8, 0, base as u8, false);
+        self.code.push(0x8B);
+        self.modrm_disp(dst as u8, base, disp);
+    }
+
+    // lea dst, [base + disp]
+    pub fn lea(&mut self, dst: Reg, base: Reg, disp: i32) {
+        self.rex(true, dst as u8, 0, base as u8, false);
+        self.code.push(0x8D);
+        self.modrm_disp(dst as u8, base, disp);
+    }
+
+    // lea dst, [base + index]
+    pub fn lea_index(&mut self, dst: Reg, base: Reg, index: Reg) {
+        self.rex(true, dst as u8, index as u8, base as u8, false);
+        self.code.push(0x8D);
+        self.modrm_index(dst as u8, base, index);
+    }
+
+    // add dst, imm32 (64-bit)
+    pub fn add_imm(&mut self, dst: Reg, imm: i32) {
+        self.rex(true, 0, 0, dst as u8, false);
+        self.code.push(0x81);
+        self.modrm_rr(0, dst as u8);
+        self.bytes(&imm.to_le_bytes());
+    }
+
+    pub fn inc(&mut self, dst: Reg) {
+        self.rex(true, 0, 0, dst as u8, false);
+        self.code.push(0xFF);
+        self.modrm_rr(0, dst as u8);
+    }
+
+    pub fn dec(&mut self, dst: Reg) {
+        self.rex(true, 0, 0, dst as u8, false);
+        self.code.push(0xFF);
+        self.modrm_rr(1, dst as u8);
+    }
+
+    // sub dst, src (64-bit)
+    pub fn sub(&mut self, dst: Reg, src: Reg) {
+        self.rex(true, src as u8, 0, dst as u8, false);
+        self.code.push(0x29);
+        self.modrm_rr(src as u8, dst as u8);
+    }
+
+    // xor dst, src (32-bit, clearing the upper half)
+    pub fn xor32(&mut self, dst: Reg, src: Reg) {
+        self.rex(false, src as u8, 0, dst as u8, false);
+        self.code.push(0x31);
+        self.modrm_rr(src as u8, dst as u8);
+    }
+
+    // Unsigned divide edx:eax by src, leaving the quotient in eax and the remainder in edx
+    pub fn div32(&mut self, src: Reg) {
+        self.rex(false, 0, 0, src as u8, false);
+        self.code.push(0xF7);
+        self.modrm_rr(6, src as u8);
+    }
+
+    // cmp a, b (64-bit)
+    pub fn cmp(&mut self, a: Reg, b: Reg) {
+        self.rex(true, b as u8, 0, a as u8, false);
+        self.code.push(0x39);
+        self.modrm_rr(b as u8, a as u8);
+    }
+
+    // cmp dst, imm32 (32-bit)
+    pub fn cmp_imm32(&mut self, dst: Reg, imm: u32) {
+        self.rex(false, 0, 0, dst as u8, false);
+        self.code.push(0x81);
+        self.modrm_rr(7, dst as u8);
+        self.bytes(&imm.to_le_bytes());
+    }
+
+    // test a, b (64-bit)
+    pub fn test(&mut self, a: Reg, b: Reg) {
+        self.rex(true, b as u8, 0, a as u8, false);
+        self.code.push(0x85);
+        self.modrm_rr(b as u8, a as u8);
+    }
+
+    // imul dst, src, imm32 (32-bit)
+    pub fn imul_imm(&mut self, dst: Reg, src: Reg, imm: i32) {
+        self.rex(false, dst as u8, 0, src as u8, false);
+        self.code.push(0x69);
+        self.modrm_rr(dst as u8, src as u8);
+        self.bytes(&imm.to_le_bytes());
+    }
+
+    // mov dst, dword [table + index * 4] (zero-extended)
+    pub fn load_table32(&mut self, dst: Reg, table: i32, index: Reg) {
+        self.rex(false, dst as u8, index as u8, 0, false);
+        self.code.push(0x8B);
+        self.code.push((dst as u8 & 7) << 3 | 0x4);
+        self.code.push(0x80 | (index as u8 & 7) << 3 | 0x5);
+        self.bytes(&table.to_le_bytes());
+    }
+
+    // mov byte [base + disp], src8
+    pub fn store_byte_disp(&mut self, base: Reg, disp: i32, src: Reg) {
+        self.rex(false, src as u8, 0, base as u8, src as u8 >= 4);
+        self.code.push(0x88);
+        self.modrm_disp(src as u8, base, disp);
+    }
+
+    // add byte [base + index], imm8
+    pub fn add_byte_imm(&mut self, base: Reg, index: Reg, imm: u8) {
+        self.rex(false, 0, index as u8, base as u8, false);
+        self.code.push(0x80);
+        self.modrm_index(0, base, index);
+        self.code.push(imm);
+    }
+
+    // cmp byte [base + index], imm8
+    pub fn cmp_byte_imm(&mut self, base: Reg, index: Reg, imm: u8) {
+        self.rex(false, 0, index as u8, base as u8, false);
+        self.code.push(0x80);
+        self.modrm_index(7, base, index);
+        self.code.push(imm);
+    }
+
+    // mov byte [base + index], imm8
+    pub fn store_byte_imm(&mut self, base: Reg, index: Reg, imm: u8) {
+        self.rex(false, 0, index as u8, base as u8, false);
+        self.code.push(0xC6);
+        self.modrm_index(0, base, index);
+        self.code.push(imm);
+    }
+
+    // mov byte [base + index], src8
+    pub fn store_byte(&mut self, base: Reg, index: Reg, src: Reg) {
+        self.rex(false, src as u8, index as u8, base as u8, src as u8 >= 4);
+        self.code.push(0x88);
+        self.modrm_index(src as u8, base, index);
+    }
+
+    // add byte [base + index], src8
+    pub fn add_byte(&mut self, base: Reg, index: Reg, src: Reg) {
+        self.rex(false, src as u8, index as u8, base as u8, src as u8 >= 4);
+        self.code.push(0x00);
+        self.modrm_index(src as u8, base, index);
+    }
+
+    // movzx dst, byte [base + index]
+    pub fn load_byte(&mut self, dst: Reg, base: Reg, index: Reg) {
+        self.rex(false, dst as u8, index as u8, base as u8, false);
+        self.bytes(&[0x0F, 0xB6]);
+        self.modrm_index(dst as u8, base, index);
+    }
+
+    pub fn jmp(&mut self, label: Label) {
+        self.code.push(0xE9);
+        self.rel32(label);
+    }
+
+    pub fn jcc(&mut self, cond: Cond, label: Label) {
+        self.bytes(&[0x0F, 0x80 | cond as u8]);
+        self.rel32(label);
+    }
+
+    fn rel32(&mut self, label: Label) {
+        self.fixups.push((self.code.len(), label));
+        self.bytes(&[0; 4]);
+    }
+}
+
+// How a target performs I/O. Each hook is emitted inline with the cell at `[rbx + r12]` and may clobber the scratch
+// registers.
+pub trait Io {
+    // Also responsible for counting the step, since a failed read shouldn't count as one
+    fn input(&mut self, asm: &mut Assembler, op: usize);
+    fn output(&mut self, asm: &mut Assembler, op: usize);
+    fn debug(&mut self, asm: &mut Assembler, op: usize);
+}
+
+// Returned when an op can't be encoded, e.g. an offset doesn't fit in 32 bits
+#[derive(Debug)]
+pub struct Unencodable(pub usize);
+
+// Lower a program into `asm`, which falls through to the code that follows once the program halts. Moves outside the
+// tape jump to `ptr_error` with the attempted pointer in rax and the op index in rcx.
+pub fn lower(prog: &Program, asm: &mut Assembler, io: &mut impl Io, ptr_error: Label) -> Result<(), Unencodable> {
+    use Reg::*;
+
+    let starts = (0..=prog.len()).map(|_| asm.new_label()).collect::<Vec<_>>();
+    let mut stubs = Vec::new();
+    let mut bounds_check = |asm: &mut Assembler, idx: usize| {
+        let stub = asm.new_label();
+        asm.cmp(Rax, R15);
+        asm.jcc(Cond::Ae, stub);
+        stubs.push((stub, idx));
+    };
+    let done = asm.new_label();
+
+    for (idx, op) in prog.ops().iter().enumerate() {
+        asm.bind(starts[idx]);
+        if *op != Op::In {
+            asm.inc(R13);
+        }
+        let imm = |n: isize| i32::try_from(n).map_err(|_| Unencodable(idx));
+
+        match *op {
+            Op::Add(n) => asm.add_byte_imm(Rbx, R12, n as u8),
+            Op::Move(n) => {
+                asm.lea(Rax, R12, imm(n)?);
+                bounds_check(asm, idx);
+                asm.mov(R12, Rax);
+            },
+            Op::Clear => asm.store_byte_imm(Rbx, R12, 0),
+            Op::MulAdd { offset, factor } => {
+                let skip = asm.new_label();
+                asm.load_byte(Rcx, Rbx, R12);
+                asm.test(Rcx, Rcx);
+                asm.jcc(Cond::E, skip);
+                asm.lea(Rax, R12, imm(offset)?);
+                bounds_check(asm, idx);
+                asm.imul_imm(Rcx, Rcx, factor);
+                asm.add_byte(Rbx, Rax, Rcx);
+                asm.bind(skip);
+            },
+            Op::ScanLeft(stride) | Op::ScanRight(stride) => {
+                let stride = if let Op::ScanLeft(_) = op { -imm(stride as isize)? } else { imm(stride as isize)? };
+                let head = asm.new_label();
+                let exit = asm.new_label();
+                asm.bind(head);
+                asm.cmp_byte_imm(Rbx, R12, 0);
+                asm.jcc(Cond::E, exit);
+                asm.lea(Rax, R12, stride);
+                bounds_check(asm, idx);
+                asm.mov(R12, Rax);
+                asm.jmp(head);
+                asm.bind(exit);
+            },
+            Op::Out => io.output(asm, idx),
+            Op::In => io.input(asm, idx),
+            Op::Open(end) => {
+                asm.cmp_byte_imm(Rbx, R12, 0);
+                asm.jcc(Cond::E, starts[end]);
+            },
+            Op::Close(start) => {
+                asm.cmp_byte_imm(Rbx, R12, 0);
+                asm.jcc(Cond::Ne, starts[start]);
+            },
+            Op::Debug => io.debug(asm, idx),
+        }
+    }
+    asm.bind(starts[prog.len()]);
+    asm.jmp(done);
+
+    for (stub, idx) in stubs {
+        asm.bind(stub);
+        asm.mov_imm32(Rcx, idx as u32);
+        asm.jmp(ptr_error);
+    }
+    asm.bind(done);
+    Ok(())
+}
@@ -1,7 +1,21 @@
-use std::fmt;
-use crate::ir::{
-    OpKind,
-    lir,
+use std::{
+    collections::{
+        BTreeMap,
+        HashMap,
+    },
+    fmt,
+};
+use crate::{
+    ir::{
+        OpKind,
+        lir,
+    },
+    vm::{
+        Cell,
+        DebugHook,
+        Dialect,
+        Tape,
+    },
 };
 
 // Memory model
@@ -10,6 +24,9 @@ use crate::ir::{
 // XYXYXYXYXYXYXY
 // X = stack data
 // Y = scratch
+//
+// Local offsets from the LIR index the stack data, so offset n lives in cell 2n. Cell 1 holds the id of the block
+// being executed, or 0 once the program exits.
 
 #[derive(Debug)]
 pub struct Program {
@@ -108,9 +125,8 @@ impl Program {
                 lir::Branch::Goto(id) => instrs.push(Instr::Goto(id)),
                 lir::Branch::If(pred, if_true, if_false) =>
                     instrs.push(Instr::If(pred, if_true, if_false)),
-                lir::Branch::Return(stack_size) =>
+                lir::Branch::Return(_stack_size) =>
                     instrs.push(Instr::Return),
-                _ => unimplemented!(),
             }
 
             blocks.push(Block {
@@ -147,6 +163,44 @@ impl Program {
         s += ">]<";
         s
     }
+
+    // Like `to_bf`, but in another dialect and without the block markers
+    pub fn to_dialect(&self, dialect: &Dialect) -> String {
+        dialect.emit(&self.to_bf())
+    }
+}
+
+// Format the tape according to the memory model, with one row per stack slot showing its data cell, the scratch
+// cell after it and the names of the locals at that offset (see `lir::Program::local_offsets`)
+pub fn format_tape<C: Cell>(tape: &Tape<C>, locals: &HashMap<String, usize>) -> String {
+    let mut names = BTreeMap::<usize, Vec<&str>>::new();
+    for (name, offs) in locals {
+        names.entry(*offs).or_default().push(name);
+    }
+    names.values_mut().for_each(|names| names.sort_unstable());
+    let slots = names.keys().next_back().map_or(0, |offs| offs + 1).max(SCRATCH_3 / SKIP + 1);
+
+    let mut s = format!("block {}\n", tape.get(1));
+    s += "slot   data  scratch  locals\n";
+    for slot in 0..slots {
+        let cell = (slot * SKIP) as isize;
+        // The scratch cell of the first slot is the block id
+        let scratch = if slot == 0 { "-".to_string() } else { tape.get(cell + 1).to_string() };
+        let names = names.get(&slot).map(|names| names.join(", ")).unwrap_or_default();
+        let row = format!("{:>4}  {:>5}  {:>7}  {}", slot, tape.get(cell), scratch, names);
+        s += row.trim_end();
+        s += "\n";
+    }
+    s
+}
+
+// Prints the tape with `format_tape` to stderr, an alternative to `vm::TapeDump` for programs generated from LIR
+pub struct LayoutDump(pub HashMap<String, usize>);
+
+impl<C: Cell> DebugHook<C> for LayoutDump {
+    fn debug(&mut self, tape: &Tape<C>, tape_ptr: isize, code_pos: usize) {
+        eprint!("[{}] ptr={}, {}", code_pos, tape_ptr, format_tape(tape, &self.0));
+    }
 }
 
 struct Repeat(char, usize);
@@ -186,18 +240,6 @@ fn add_byte_zeroing(from: usize, to: usize) -> String {
     )
 }
 
-fn sub_byte_zeroing(from: usize, to: usize) -> String {
-    format!(
-        "{}[-{}{}-{}{}]{}",
-        Repeat('>', from),
-        Repeat('<', from),
-        Repeat('>', to),
-        Repeat('<', to),
-        Repeat('>', from),
-        Repeat('<', from),
-    )
-}
-
 fn zero_byte(offs: usize) -> String {
     format!(
         "{}[-]{}",
@@ -320,17 +362,17 @@ impl Instr {
                 + &add_byte_preserve(*pred * 2, SCRATCH_1, SCRATCH_2)
                 + &set_byte(SCRATCH_2, 0)
                 + &format!(
-                    "{}{}[{}{}{}]{}",
+                    "{}{}[{}{}{}[-]]{}",
                     set_byte(1, *if_false as u8),
                     Repeat('>', SCRATCH_1),
                     Repeat('<', SCRATCH_1),
                     set_byte(1, *if_true as u8),
-                    Repeat('>', SCRATCH_2),
-                    Repeat('<', SCRATCH_2),
+                    Repeat('>', SCRATCH_1),
+                    Repeat('<', SCRATCH_1),
                 )
             },
             Instr::Return => {
-                format!("<+[-[>>->>]<<+]<<<")
+                "<+[-[>>->>]<<+]<<<".to_string()
             },
         }
     }
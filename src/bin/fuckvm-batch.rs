@@ -0,0 +1,113 @@
+use std::{
+    env,
+    fs::{
+        self,
+        File,
+    },
+    io::{
+        BufWriter,
+        Write,
+    },
+    path::Path,
+    process,
+};
+use fuckvm::vm::{
+    Batch,
+    Case,
+    Outcome,
+};
+
+const USAGE: &str = "\
+usage: fuckvm-batch [options] <manifest>
+options:
+  --threads <n>       number of worker threads (default: one per CPU)
+  --fuel <n>          maximum steps per case
+  --results <file>    write one JSON result per line to a file
+
+Each line of the manifest is a case: a program followed optionally by an input file and a file holding the
+expected output, where `-` means no input or no check. Paths are relative to the manifest, and blank lines and lines
+starting with `#` are ignored.";
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(1);
+}
+
+fn main() {
+    let mut batch = Batch::new();
+    let mut results_path = None;
+    let mut manifest = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--threads" => batch = batch.with_threads(value().parse().unwrap_or_else(|_| usage())),
+            "--fuel" => batch = batch.with_fuel(value().parse().unwrap_or_else(|_| usage())),
+            "--results" => results_path = Some(value()),
+            _ if manifest.is_none() => manifest = Some(arg),
+            _ => usage(),
+        }
+    }
+
+    let manifest = manifest.unwrap_or_else(|| usage());
+    let dir = Path::new(&manifest).parent().unwrap_or_else(|| Path::new(""));
+    let read = |path: &str| fs::read(dir.join(path)).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path, err);
+        process::exit(1);
+    });
+
+    let manifest_text = fs::read_to_string(&manifest).unwrap_or_else(|err| {
+        eprintln!("{}: {}", manifest, err);
+        process::exit(1);
+    });
+    for line in manifest_text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let (program, input, expected) = match words.as_slice() {
+            [program] => (program, "-", "-"),
+            [program, input] => (program, *input, "-"),
+            [program, input, expected] => (program, *input, *expected),
+            _ => {
+                eprintln!("invalid manifest line: {}", line);
+                process::exit(1);
+            },
+        };
+        batch.add_case(Case {
+            name: if input == "-" { program.to_string() } else { format!("{} < {}", program, input) },
+            code: String::from_utf8_lossy(&read(program)).into_owned(),
+            input: if input == "-" { Vec::new() } else { read(input) },
+            expected: if expected == "-" { None } else { Some(read(expected)) },
+        });
+    }
+
+    let report = batch.run();
+    for result in &report.results {
+        match &result.outcome {
+            Outcome::Passed => {},
+            Outcome::Failed(_) => println!("FAIL {}: unexpected output", result.name),
+            Outcome::OutOfFuel => println!("FAIL {}: out of fuel after {} steps", result.name, result.steps),
+            Outcome::Error(err) => println!("FAIL {}: {}", result.name, err),
+        }
+    }
+    println!("{}", report);
+
+    if let Some(path) = results_path {
+        let written = File::create(&path).and_then(|file| {
+            let mut file = BufWriter::new(file);
+            report.write_json_lines(&mut file)?;
+            file.flush()
+        });
+        if let Err(err) = written {
+            eprintln!("{}: {}", path, err);
+            process::exit(1);
+        }
+    }
+
+    if !report.all_passed() {
+        process::exit(1);
+    }
+}
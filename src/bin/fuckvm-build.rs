@@ -0,0 +1,101 @@
+use std::{
+    env,
+    fs,
+    process,
+};
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+use fuckvm::{
+    backend::{
+        c,
+        elf,
+        wat,
+    },
+    vm::{
+        EofPolicy,
+        VmConfig,
+        VmError,
+    },
+};
+
+const USAGE: &str = "\
+usage: fuckvm-build [options] <program.bf> <output>
+options:
+  --target <c|elf|wat>                       C source, a Linux x86-64 executable or a WebAssembly text module
+                                             (default c)
+  --cell-bits <8|16|32>                      cell width (default 8)
+  --tape-len <n>                             number of tape cells (default 10000)
+  --eof <zero|minus-one|unchanged|error>     what input reads at the end of input (default zero)";
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(1);
+}
+
+fn main() {
+    let mut config = VmConfig::default();
+    let mut target = "c".to_string();
+    let mut cell_bits = 8;
+    let mut files = Vec::new();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--target" => target = value(),
+            "--cell-bits" => cell_bits = value().parse().unwrap_or_else(|_| usage()),
+            "--tape-len" => config = config.with_tape_len(value().parse().unwrap_or_else(|_| usage())),
+            "--eof" => config = config.with_eof(match value().as_str() {
+                "zero" => EofPolicy::Zero,
+                "minus-one" => EofPolicy::MinusOne,
+                "unchanged" => EofPolicy::Unchanged,
+                "error" => EofPolicy::Error,
+                _ => usage(),
+            }),
+            _ => files.push(arg),
+        }
+    }
+
+    let (code, output) = match files.as_slice() {
+        [code, output] => (
+            fs::read_to_string(code).unwrap_or_else(|err| {
+                eprintln!("{}: {}", code, err);
+                process::exit(1);
+            }),
+            output,
+        ),
+        _ => usage(),
+    };
+
+    let result: Result<Vec<u8>, VmError> = match (target.as_str(), cell_bits) {
+        ("c", 8) => c::transpile::<u8>(&code, &config).map(String::into_bytes),
+        ("c", 16) => c::transpile::<u16>(&code, &config).map(String::into_bytes),
+        ("c", 32) => c::transpile::<u32>(&code, &config).map(String::into_bytes),
+        ("wat", 8) => wat::transpile::<u8>(&code, &config).map(String::into_bytes),
+        ("wat", 16) => wat::transpile::<u16>(&code, &config).map(String::into_bytes),
+        ("wat", 32) => wat::transpile::<u32>(&code, &config).map(String::into_bytes),
+        ("elf", 8) => elf::assemble(&code, &config),
+        ("elf", _) => Err(VmError::Unsupported("executable output requires 8-bit cells".to_string())),
+        _ => usage(),
+    };
+
+    match result {
+        Ok(bytes) => {
+            if let Err(err) = fs::write(output, bytes) {
+                eprintln!("{}: {}", output, err);
+                process::exit(1);
+            }
+            #[cfg(unix)]
+            if target == "elf" {
+                if let Err(err) = fs::set_permissions(output, fs::Permissions::from_mode(0o755)) {
+                    eprintln!("{}: {}", output, err);
+                    process::exit(1);
+                }
+            }
+        },
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        },
+    }
+}
@@ -0,0 +1,255 @@
+use std::{
+    env,
+    fs,
+    io::{
+        stdin,
+        stdout,
+        BufRead,
+        Write,
+    },
+    process,
+};
+use fuckvm::{
+    vm::{
+        ExecStatus,
+        Op,
+        Program,
+        SourceLoc,
+        Vm,
+        WatchKind,
+    },
+    Error,
+};
+
+const HELP: &str = "\
+commands:
+  break <pos>      set a breakpoint at a source offset
+  delete <pos>     remove a breakpoint
+  step             execute a single op
+  next             execute a single op, running loops to completion
+  finish           run until the current loop exits
+  continue         run until a breakpoint, watchpoint or the end of the program
+  tape <a>..<b>    show a range of tape cells
+  watch <c> [n]    stop when cell c is written, or only when it's set to n
+  rwatch <cell>    stop when a cell is read
+  unwatch <cell>   remove the watchpoints on a cell
+  where            show the current position
+  restart          start the program again with a fresh tape
+  quit";
+
+struct Debugger {
+    code: String,
+    prog: Program,
+    vm: Vm,
+    input: Vec<u8>,
+    input_pos: usize,
+}
+
+impl Debugger {
+    fn new(code: String, input: Vec<u8>) -> Result<Self, Error> {
+        let prog = Vm::compile(&code).map_err(Error::VmError)?;
+        let mut vm = Vm::new();
+        vm.load(&prog)?;
+        Ok(Self {
+            code,
+            prog,
+            vm,
+            input,
+            input_pos: 0,
+        })
+    }
+
+    fn restart(&mut self) {
+        let breakpoints = self.vm.breakpoints().collect::<Vec<_>>();
+        let watchpoints = self.vm.watchpoints().collect::<Vec<_>>();
+        self.vm = Vm::new();
+        // The program was already loaded once with the same config
+        self.vm.load(&self.prog).unwrap();
+        breakpoints.into_iter().for_each(|pos| self.vm.add_breakpoint(pos));
+        watchpoints.into_iter().for_each(|(cell, kind)| self.vm.add_watchpoint(cell, kind));
+        self.input_pos = 0;
+    }
+
+    // Handle I/O until execution stops. A `single` op is complete once it has produced output, otherwise execution
+    // carries on until the VM stops for some other reason.
+    fn drive(&mut self, single: bool, f: impl Fn(&mut Vm) -> Result<ExecStatus, Error>) -> Result<ExecStatus, Error> {
+        let mut status = f(&mut self.vm)?;
+        loop {
+            match status {
+                ExecStatus::Output(b) => {
+                    stdout().write_all(&[b]).unwrap();
+                    stdout().flush().unwrap();
+                    if single {
+                        return Ok(ExecStatus::Suspended);
+                    }
+                    status = self.vm.advance(None)?;
+                },
+                ExecStatus::NeedsInput => {
+                    match self.input.get(self.input_pos) {
+                        Some(b) => {
+                            self.vm.provide_input(*b);
+                            self.input_pos += 1;
+                        },
+                        None => self.vm.provide_eof(),
+                    }
+                    status = if single { f(&mut self.vm)? } else { self.vm.advance(None)? };
+                },
+                status => return Ok(status),
+            }
+        }
+    }
+
+    fn location(&self) -> String {
+        let pos = match self.vm.code_pos() {
+            Some(pos) => pos,
+            None => return "<end of program>".to_string(),
+        };
+        let loc = SourceLoc::locate(&self.code, pos);
+        format!("{}, op {:?}\n{}", loc, self.vm.current_op().unwrap(), loc.excerpt(&self.code))
+    }
+
+    fn report(&self, status: ExecStatus) {
+        match status {
+            ExecStatus::Halted => println!("program halted after {} steps", self.vm.steps()),
+            ExecStatus::Breakpoint(pos) => println!("breakpoint at offset {}", pos),
+            ExecStatus::Watchpoint(hit) => println!("watchpoint: {}", hit),
+            _ => {},
+        }
+        if !self.vm.is_halted() {
+            println!("{}", self.location());
+        }
+    }
+
+    fn tape(&self, range: &str) {
+        let mut parts = range.splitn(2, "..");
+        let start = parts.next().and_then(|s| s.trim().parse::<isize>().ok());
+        let end = parts.next().and_then(|s| s.trim().parse::<isize>().ok());
+        let (start, end) = match (start, end) {
+            (Some(start), Some(end)) => (start, end),
+            (Some(start), None) => (start, start + 1),
+            _ => (0, 20),
+        };
+        for pos in start..end {
+            let marker = if pos == self.vm.tape_ptr() { " <-" } else { "" };
+            println!("{:>6}: {:>3}{}", pos, self.vm.tape().get(pos), marker);
+        }
+    }
+
+    fn command(&mut self, line: &str) -> Result<bool, Error> {
+        let mut words = line.split_whitespace();
+        let cmd = match words.next() {
+            Some(cmd) => cmd,
+            None => return Ok(true),
+        };
+        let arg = words.next();
+        let num = arg.and_then(|arg| arg.parse::<usize>().ok());
+        let value = words.next().and_then(|arg| arg.parse::<i64>().ok());
+
+        match (cmd, num) {
+            ("break" | "b", Some(pos)) => {
+                self.vm.add_breakpoint(pos);
+                println!("breakpoint set at offset {}", pos);
+            },
+            ("delete" | "d", Some(pos)) => if !self.vm.remove_breakpoint(pos) {
+                println!("no breakpoint at offset {}", pos);
+            },
+            ("step" | "s", _) => {
+                let status = self.drive(true, |vm| vm.single_step())?;
+                self.report(status);
+            },
+            ("next" | "n", _) => {
+                let single = !matches!(self.vm.current_op(), Some(Op::Open(_)));
+                let status = self.drive(single, |vm| vm.step_over_loop())?;
+                self.report(status);
+            },
+            ("finish" | "f", _) => {
+                let status = self.drive(false, |vm| vm.run_to_loop_exit())?;
+                self.report(status);
+            },
+            ("continue" | "c", _) => {
+                let status = self.drive(false, |vm| vm.advance(None))?;
+                self.report(status);
+            },
+            ("tape" | "t", _) => self.tape(arg.unwrap_or("")),
+            ("watch" | "w", Some(cell)) => {
+                let kind = value.map_or(WatchKind::Write, WatchKind::WriteValue);
+                self.vm.add_watchpoint(cell as isize, kind);
+                match value {
+                    Some(value) => println!("watching cell {} for writes of {}", cell, value),
+                    None => println!("watching cell {} for writes", cell),
+                }
+            },
+            ("rwatch", Some(cell)) => {
+                self.vm.add_watchpoint(cell as isize, WatchKind::Read);
+                println!("watching cell {} for reads", cell);
+            },
+            ("unwatch", Some(cell)) => {
+                let kinds = self.vm.watchpoints().filter(|w| w.0 == cell as isize).collect::<Vec<_>>();
+                if kinds.is_empty() {
+                    println!("no watchpoint on cell {}", cell);
+                }
+                kinds.into_iter().for_each(|(cell, kind)| {
+                    self.vm.remove_watchpoint(cell, kind);
+                });
+            },
+            ("where", _) => {
+                println!("tape pointer {}, {} steps", self.vm.tape_ptr(), self.vm.steps());
+                if self.vm.is_halted() {
+                    println!("<end of program>");
+                } else {
+                    println!("{}", self.location());
+                }
+            },
+            ("restart", _) => self.restart(),
+            ("quit" | "q", _) => return Ok(false),
+            _ => println!("{}", HELP),
+        }
+        Ok(true)
+    }
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let (code, input) = match (args.next(), args.next()) {
+        (Some(code), input) => (code, input),
+        _ => {
+            eprintln!("usage: fuckvm-dbg <program.bf> [input file]");
+            process::exit(1);
+        },
+    };
+
+    let code = fs::read_to_string(&code).unwrap_or_else(|err| {
+        eprintln!("{}: {}", code, err);
+        process::exit(1);
+    });
+    let input = input
+        .map(|input| fs::read(&input).unwrap_or_else(|err| {
+            eprintln!("{}: {}", input, err);
+            process::exit(1);
+        }))
+        .unwrap_or_default();
+
+    let mut dbg = match Debugger::new(code, input) {
+        Ok(dbg) => dbg,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        },
+    };
+
+    let stdin = stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("(fuckvm) ");
+        stdout().flush().unwrap();
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break,
+        };
+        match dbg.command(&line) {
+            Ok(true) => {},
+            Ok(false) => break,
+            Err(err) => println!("error: {}", err),
+        }
+    }
+}
@@ -11,7 +11,10 @@ use fuckvm::{
         },
     },
     bf::bfir,
-    vm::Vm,
+    vm::{
+        Vm,
+        VmConfig,
+    },
 };
 
 fn main() {
@@ -64,12 +67,11 @@ fn main() {
 
     println!("BF: {}", bf);
 
-    let mut vm = Vm::new();
+    let mut vm: Vm = Vm::with_config(VmConfig::default().with_profile(true));
+
+    vm.exec(&bf).unwrap();
 
-    vm.exec(&bf);
+    println!("{}", vm.profile().unwrap().report(&bf, 10));
 
-    for i in 0..20 {
-        print!("{}, ", vm.get(i))
-    }
-    print!("\n");
+    print!("{}", bfir::format_tape(vm.tape(), lir.local_offsets("main").unwrap()));
 }
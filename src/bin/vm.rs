@@ -1,7 +1,124 @@
-use fuckvm::vm::Vm;
+use std::{
+    env,
+    fs::{
+        self,
+        File,
+    },
+    io::{
+        stdin,
+        stdout,
+    },
+    process,
+};
+use fuckvm::{
+    vm::{
+        Dialect,
+        Program,
+        Recording,
+        SourceLoc,
+        Vm,
+        VmConfig,
+    },
+    Error,
+};
+
+const HELLO_WORLD: &str = r#"++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++."#;
+
+const USAGE: &str = "\
+usage: vm [options] [program]
+options:
+  --dialect <ook | map>    read the program in Ook! or the dialect described by a token map file
+  --record <log>           log input and output so that the run can be replayed
+  --replay <log>           run with the input from a log, checking the output against it
+  --debug-chars <chars>    characters that print the first 20 tape cells, such as ':'
+
+A token map has one command per line followed by its token, such as `+ inc`.";
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(1);
+}
+
+// Show where in the source a runtime error happened
+fn print_location(code: &str, err: &Error) {
+    if let Error::VmError(err) = err {
+        if let Some(pos) = err.code_pos() {
+            let loc = SourceLoc::locate(code, pos);
+            eprintln!("at {}\n{}", loc, loc.excerpt(code));
+        }
+    }
+}
 
 fn main() {
-    let mut vm = Vm::new();
+    let mut config = VmConfig::default();
+    let mut dialect = None;
+    let mut record = None;
+    let mut replay = None;
+    let mut file = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--dialect" => dialect = Some(value()),
+            "--record" if replay.is_none() => record = Some(value()),
+            "--replay" if record.is_none() => replay = Some(value()),
+            "--debug-chars" => config = config.with_debug_chars(&value().chars().collect::<Vec<_>>()),
+            _ if file.is_none() && !arg.starts_with("--") => file = Some(arg),
+            _ => usage(),
+        }
+    }
+
+    let read = |file: &str| fs::read_to_string(file).unwrap_or_else(|err| {
+        eprintln!("{}: {}", file, err);
+        process::exit(1);
+    });
+    match dialect.as_deref() {
+        Some("ook") => config = config.with_dialect(Dialect::ook()),
+        Some(map) => match Dialect::parse(&read(map)) {
+            Ok(dialect) => config = config.with_dialect(dialect),
+            Err(err) => {
+                eprintln!("{}: {}", map, err);
+                process::exit(1);
+            },
+        },
+        None => {},
+    }
+    let code = match file {
+        Some(file) => read(&file),
+        None if dialect.is_none() => HELLO_WORLD.to_string(),
+        None => usage(),
+    };
+
+    let mut vm: Vm = Vm::with_config(config);
+    let prog = match Program::compile_for(&code, vm.config()) {
+        Ok(prog) => prog,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        },
+    };
 
-    vm.exec(r#"++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++."#).unwrap();
+    if let Some(log) = record {
+        let mut log = File::create(&log).unwrap_or_else(|err| {
+            eprintln!("{}: {}", log, err);
+            process::exit(1);
+        });
+        // The log is complete even if the run fails, so it can be replayed to reproduce the failure
+        if let Err(err) = vm.run_recorded(&prog, &mut stdin().lock(), &mut stdout().lock(), &mut log) {
+            eprintln!("{}", err);
+            print_location(&code, &err);
+            process::exit(1);
+        }
+    } else if let Some(log) = replay {
+        if let Err(err) = Recording::load(log).and_then(|rec| vm.replay(&prog, &rec, &mut stdout().lock())) {
+            eprintln!("\nreplay failed: {}", err);
+            print_location(&code, &err);
+            process::exit(1);
+        }
+    } else if let Err(err) = vm.run(&prog) {
+        eprintln!("{}", err);
+        print_location(&code, &err);
+        process::exit(1);
+    }
 }
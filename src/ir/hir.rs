@@ -74,6 +74,12 @@ impl Program {
     }
 }
 
+impl Default for Program {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Function {
     pub fn new(output: Type, input: (impl Into<String>, Type)) -> Self {
         Self {
@@ -18,6 +18,8 @@ pub enum LirError {
 pub struct Program {
     pub(crate) entry_id: usize,
     pub(crate) blocks: HashMap<usize, Block>,
+    // Offsets of each function's locals within its stack frame
+    pub(crate) locals: HashMap<String, HashMap<String, usize>>,
 }
 
 #[derive(Debug)]
@@ -75,7 +77,7 @@ impl Program {
 
             // Generate block IDs
             for (block_name, block) in &func.blocks {
-                let block_name = gen_block_name(&func_name, &block_name);
+                let block_name = gen_block_name(func_name, block_name);
                 let block_id = id_gen.next();
                 block_ids.insert(block_name, block_id);
 
@@ -113,12 +115,12 @@ impl Program {
                     match op {
                         hir::Op::Unary(tgt, kind, arg) => ops.push(Op::Unary(
                             local_to_offs(&tgt.0),
-                            kind.clone(),
+                            *kind,
                             local_to_offs(&arg.0),
                         )),
                         hir::Op::Binary(tgt, kind, arg0, arg1) => ops.push(Op::Binary(
                             local_to_offs(&tgt.0),
-                            kind.clone(),
+                            *kind,
                             local_to_offs(&arg0.0),
                             local_to_offs(&arg1.0),
                         )),
@@ -162,7 +164,6 @@ impl Program {
                             *block_ids.get(&gen_block_name(func_name, if_false)).unwrap(),
                         )
                     },
-                    _ => unimplemented!(),
                 };
 
                 let block_id = *block_ids.get(&gen_block_name(func_name, block_name)).unwrap();
@@ -179,6 +180,11 @@ impl Program {
         Ok(Program {
             entry_id: *block_ids.get(&gen_block_name("main", "entry")).unwrap(),
             blocks,
+            locals: func_offs.into_iter().map(|(name, (offs, _))| (name, offs)).collect(),
         })
     }
+
+    pub fn local_offsets(&self, func: &str) -> Option<&HashMap<String, usize>> {
+        self.locals.get(func)
+    }
 }
@@ -1,3 +1,6 @@
+use std::fmt;
+
+pub mod backend;
 pub mod bf;
 pub mod ir;
 pub mod vm;
@@ -7,3 +10,12 @@ pub enum Error {
     VmError(vm::VmError),
     Lir(ir::lir::LirError),
 }
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::VmError(err) => write!(f, "{}", err),
+            Error::Lir(err) => write!(f, "{:?}", err),
+        }
+    }
+}
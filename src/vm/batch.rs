@@ -0,0 +1,247 @@
+use std::{
+    fmt,
+    io::{
+        self,
+        Write,
+    },
+    sync::{
+        atomic::{
+            AtomicUsize,
+            Ordering,
+        },
+        Mutex,
+    },
+    thread,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+use crate::Error;
+use super::{
+    ExecStatus,
+    Program,
+    Vm,
+    VmConfig,
+};
+
+pub struct Case {
+    pub name: String,
+    pub code: String,
+    pub input: Vec<u8>,
+    // The output isn't checked if this is `None`
+    pub expected: Option<Vec<u8>>,
+}
+
+#[derive(Debug)]
+pub enum Outcome {
+    Passed,
+    // The program halted with unexpected output, the expected output is kept for reporting
+    Failed(Vec<u8>),
+    OutOfFuel,
+    Error(Error),
+}
+
+impl Outcome {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Outcome::Passed => "passed",
+            Outcome::Failed(_) => "failed",
+            Outcome::OutOfFuel => "out_of_fuel",
+            Outcome::Error(_) => "error",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CaseResult {
+    pub name: String,
+    pub outcome: Outcome,
+    pub output: Vec<u8>,
+    pub steps: u64,
+    pub elapsed: Duration,
+}
+
+// Runs many cases across a pool of threads, each case on a fresh `Vm`
+pub struct Batch {
+    config: VmConfig,
+    threads: usize,
+    fuel: Option<u64>,
+    cases: Vec<Case>,
+}
+
+impl Default for Batch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Batch {
+    pub fn new() -> Self {
+        Self {
+            config: VmConfig::default(),
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            fuel: None,
+            cases: Vec::new(),
+        }
+    }
+
+    pub fn with_config(mut self, config: VmConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    // The number of steps each case may take before it's stopped and counted as `Outcome::OutOfFuel`
+    pub fn with_fuel(mut self, fuel: u64) -> Self {
+        self.fuel = Some(fuel);
+        self
+    }
+
+    pub fn with_case(mut self, case: Case) -> Self {
+        self.add_case(case);
+        self
+    }
+
+    pub fn add_case(&mut self, case: Case) {
+        self.cases.push(case);
+    }
+
+    // Results are reported in the order the cases were added
+    pub fn run(&self) -> Report {
+        let start = Instant::now();
+        let next = AtomicUsize::new(0);
+        let results = Mutex::new(Vec::with_capacity(self.cases.len()));
+
+        thread::scope(|s| {
+            for _ in 0..self.threads.min(self.cases.len()) {
+                s.spawn(|| loop {
+                    let idx = next.fetch_add(1, Ordering::Relaxed);
+                    match self.cases.get(idx) {
+                        Some(case) => {
+                            let result = self.run_case(case);
+                            results.lock().unwrap().push((idx, result));
+                        },
+                        None => break,
+                    }
+                });
+            }
+        });
+
+        let mut results = results.into_inner().unwrap();
+        results.sort_by_key(|(idx, _)| *idx);
+        Report {
+            results: results.into_iter().map(|(_, result)| result).collect(),
+            elapsed: start.elapsed(),
+        }
+    }
+
+    fn run_case(&self, case: &Case) -> CaseResult {
+        let start = Instant::now();
+        let mut vm = Vm::<u8>::with_config(self.config.clone());
+        let mut output = Vec::new();
+
+        let outcome = match Program::compile_for(&case.code, &self.config) {
+            Ok(prog) => {
+                let fuel = self.fuel.unwrap_or(u64::MAX);
+                match vm.load(&prog).and_then(|_| vm.resume_with(fuel, &mut case.input.as_slice(), &mut output)) {
+                    Ok(ExecStatus::Halted) if case.expected.as_ref().is_none_or(|expected| *expected == output) =>
+                        Outcome::Passed,
+                    Ok(ExecStatus::Halted) => Outcome::Failed(case.expected.clone().unwrap_or_default()),
+                    Ok(_) => Outcome::OutOfFuel,
+                    Err(err) => Outcome::Error(err),
+                }
+            },
+            Err(err) => Outcome::Error(Error::VmError(err)),
+        };
+
+        CaseResult {
+            name: case.name.clone(),
+            outcome,
+            output,
+            steps: vm.steps(),
+            elapsed: start.elapsed(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Report {
+    pub results: Vec<CaseResult>,
+    pub elapsed: Duration,
+}
+
+impl Report {
+    pub fn all_passed(&self) -> bool {
+        self.results.iter().all(|result| matches!(result.outcome, Outcome::Passed))
+    }
+
+    fn count(&self, name: &str) -> usize {
+        self.results.iter().filter(|result| result.outcome.name() == name).count()
+    }
+
+    // Write one JSON object per case: name, outcome, steps, elapsed microseconds and output as a hex string, along with
+    // the expected output for failures and the message for errors
+    pub fn write_json_lines(&self, out: &mut impl Write) -> io::Result<()> {
+        for result in &self.results {
+            write!(
+                out,
+                "{{\"name\":{},\"outcome\":\"{}\",\"steps\":{},\"micros\":{},\"output\":\"{}\"",
+                json_string(&result.name),
+                result.outcome.name(),
+                result.steps,
+                result.elapsed.as_micros(),
+                hex(&result.output),
+            )?;
+            match &result.outcome {
+                Outcome::Failed(expected) => write!(out, ",\"expected\":\"{}\"", hex(expected))?,
+                Outcome::Error(err) => write!(out, ",\"error\":{}", json_string(&err.to_string()))?,
+                _ => {},
+            }
+            writeln!(out, "}}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} cases: {} passed, {} failed, {} out of fuel, {} errors ({} steps in {:.2?})",
+            self.results.len(),
+            self.count("passed"),
+            self.count("failed"),
+            self.count("out_of_fuel"),
+            self.count("error"),
+            self.results.iter().map(|result| result.steps).sum::<u64>(),
+            self.elapsed,
+        )
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json += "\\\"",
+            '\\' => json += "\\\\",
+            '\n' => json += "\\n",
+            '\r' => json += "\\r",
+            '\t' => json += "\\t",
+            c if (c as u32) < 0x20 => json += &format!("\\u{:04x}", c as u32),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
@@ -0,0 +1,60 @@
+use std::{
+    convert::TryFrom,
+    fmt,
+};
+use super::Overflow;
+
+pub trait Cell: Copy + Default + PartialEq + fmt::Debug + fmt::Display {
+    fn from_byte(b: u8) -> Self;
+    fn to_byte(self) -> u8;
+    fn to_i64(self) -> i64;
+
+    fn is_zero(self) -> bool {
+        self == Self::default()
+    }
+
+    fn wrapping_add(self, rhs: Self) -> Self;
+    fn wrapping_sub(self, rhs: Self) -> Self;
+
+    // Returns `None` if the result overflows under `Overflow::Trapping`
+    fn add_signed(self, n: i64, overflow: Overflow) -> Option<Self>;
+}
+
+macro_rules! impl_cell {
+    ($($ty:ty),*) => {
+        $(
+            impl Cell for $ty {
+                fn from_byte(b: u8) -> Self {
+                    b as Self
+                }
+
+                fn to_byte(self) -> u8 {
+                    self as u8
+                }
+
+                fn to_i64(self) -> i64 {
+                    self as i64
+                }
+
+                fn wrapping_add(self, rhs: Self) -> Self {
+                    <$ty>::wrapping_add(self, rhs)
+                }
+
+                fn wrapping_sub(self, rhs: Self) -> Self {
+                    <$ty>::wrapping_sub(self, rhs)
+                }
+
+                fn add_signed(self, n: i64, overflow: Overflow) -> Option<Self> {
+                    let val = self as i128 + n as i128;
+                    match overflow {
+                        Overflow::Wrapping => Some(val as Self),
+                        Overflow::Saturating => Some(val.clamp(0, <$ty>::MAX as i128) as Self),
+                        Overflow::Trapping => Self::try_from(val).ok(),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_cell!(u8, u16, u32);
@@ -0,0 +1,105 @@
+use super::Dialect;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TapeMode {
+    // The tape has exactly `tape_len` cells
+    Fixed,
+    // The tape starts with `tape_len` cells and grows to the right on demand
+    Growable,
+    // The tape grows in both directions on demand
+    Infinite,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Overflow {
+    Wrapping,
+    // Clamp the cell to its minimum or maximum value
+    Saturating,
+    // Stop with `VmError::CellOverflow`
+    Trapping,
+}
+
+// What `,` does when there is no more input
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EofPolicy {
+    Zero,
+    // All bits set, i.e: 255 for 8-bit cells
+    MinusOne,
+    Unchanged,
+    // Stop with `VmError::UnexpectedEof`
+    Error,
+}
+
+#[derive(Clone, Debug)]
+pub struct VmConfig {
+    pub tape_len: usize,
+    pub tape_mode: TapeMode,
+    pub overflow: Overflow,
+    pub eof: EofPolicy,
+    // Saturate the pointer at the start of the tape and ignore accesses past the end instead of failing
+    pub lenient_pointer: bool,
+    // Count how often each op and loop is executed, see `Vm::profile`
+    pub profile: bool,
+    // Characters compiled to `Op::Debug` by `Vm::exec`, usually ':' or '#'. They call the VM's debug hook, which
+    // prints the first 20 cells unless another is set.
+    pub debug_chars: Vec<char>,
+    // Source is translated from this dialect before being compiled
+    pub dialect: Option<Dialect>,
+}
+
+impl Default for VmConfig {
+    fn default() -> Self {
+        Self {
+            tape_len: 10000,
+            tape_mode: TapeMode::Fixed,
+            overflow: Overflow::Wrapping,
+            eof: EofPolicy::Zero,
+            lenient_pointer: false,
+            profile: false,
+            debug_chars: Vec::new(),
+            dialect: None,
+        }
+    }
+}
+
+impl VmConfig {
+    pub fn with_tape_len(mut self, tape_len: usize) -> Self {
+        self.tape_len = tape_len;
+        self
+    }
+
+    pub fn with_tape_mode(mut self, tape_mode: TapeMode) -> Self {
+        self.tape_mode = tape_mode;
+        self
+    }
+
+    pub fn with_overflow(mut self, overflow: Overflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    pub fn with_eof(mut self, eof: EofPolicy) -> Self {
+        self.eof = eof;
+        self
+    }
+
+    pub fn with_lenient_pointer(mut self, lenient_pointer: bool) -> Self {
+        self.lenient_pointer = lenient_pointer;
+        self
+    }
+
+    pub fn with_profile(mut self, profile: bool) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    pub fn with_debug_chars(mut self, debug_chars: &[char]) -> Self {
+        self.debug_chars = debug_chars.to_vec();
+        self
+    }
+
+    pub fn with_dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = Some(dialect);
+        self
+    }
+}
@@ -0,0 +1,212 @@
+use std::fmt;
+use super::{
+    Cell,
+    ExecStatus,
+    Op,
+    Tape,
+    Vm,
+    keeps_pause,
+};
+use crate::Error;
+
+// Called whenever a debug character (see `VmConfig::debug_chars`) is executed
+pub trait DebugHook<C: Cell> {
+    fn debug(&mut self, tape: &Tape<C>, tape_ptr: isize, code_pos: usize);
+}
+
+impl<C: Cell, F: FnMut(&Tape<C>, isize, usize)> DebugHook<C> for F {
+    fn debug(&mut self, tape: &Tape<C>, tape_ptr: isize, code_pos: usize) {
+        self(tape, tape_ptr, code_pos)
+    }
+}
+
+// Prints the first `len` cells to stderr
+pub struct TapeDump(pub usize);
+
+impl<C: Cell> DebugHook<C> for TapeDump {
+    fn debug(&mut self, tape: &Tape<C>, tape_ptr: isize, code_pos: usize) {
+        let cells = (0..self.0 as isize)
+            .map(|pos| tape.get(pos).to_string())
+            .collect::<Vec<_>>();
+        eprintln!("[{}] ptr={}: {}", code_pos, tape_ptr, cells.join(", "));
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WatchKind {
+    // Any op that reads the cell, including loop conditions and scans passing over it
+    Read,
+    // Any op that writes the cell, even if its value doesn't change
+    Write,
+    // A write that leaves the cell holding the given value
+    WriteValue(i64),
+}
+
+// Where and how a watchpoint was triggered. For reads `old` and `new` are both the value that was read.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct WatchHit {
+    pub cell: isize,
+    pub kind: WatchKind,
+    pub code_pos: usize,
+    pub step: u64,
+    pub old: i64,
+    pub new: i64,
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            WatchKind::Read =>
+                write!(f, "cell {} read at offset {} (step {}): {}", self.cell, self.code_pos, self.step, self.new),
+            _ => write!(
+                f,
+                "cell {} written at offset {} (step {}): {} -> {}",
+                self.cell,
+                self.code_pos,
+                self.step,
+                self.old,
+                self.new,
+            ),
+        }
+    }
+}
+
+impl<C: Cell> Vm<C> {
+    pub fn set_debug_hook(&mut self, hook: impl DebugHook<C> + 'static) {
+        self.debug_hook = Some(Box::new(hook));
+    }
+
+    pub fn clear_debug_hook(&mut self) {
+        self.debug_hook = None;
+    }
+
+    // Breakpoints are source positions. A breakpoint is hit before executing the first op generated from source that
+    // covers its position, so a breakpoint in a comment is never hit.
+    pub fn add_breakpoint(&mut self, code_pos: usize) {
+        self.breakpoints.insert(code_pos);
+    }
+
+    pub fn remove_breakpoint(&mut self, code_pos: usize) -> bool {
+        self.breakpoints.remove(&code_pos)
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item=usize> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    // Watchpoints are tape cells. The op that triggers one is completed before execution stops with
+    // `ExecStatus::Watchpoint`, and if it also produced output that is reported first. While there are watchpoints,
+    // simplified loops run one iteration at a time and stop after the iteration that triggered one.
+    pub fn add_watchpoint(&mut self, cell: isize, kind: WatchKind) {
+        if !self.watchpoints.contains(&(cell, kind)) {
+            self.watchpoints.push((cell, kind));
+        }
+    }
+
+    pub fn remove_watchpoint(&mut self, cell: isize, kind: WatchKind) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|w| *w != (cell, kind));
+        self.watchpoints.len() != len
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    pub fn watchpoints(&self) -> impl Iterator<Item=(isize, WatchKind)> + '_ {
+        self.watchpoints.iter().copied()
+    }
+
+    // Record the first watchpoint triggered by an access from the current op
+    pub(super) fn check_watch(&mut self, cell: isize, write: bool, old: C, new: C) {
+        if self.watch_hit.is_some() {
+            return;
+        }
+        let (old, new) = (old.to_i64(), new.to_i64());
+        let kind = self.watchpoints.iter().find_map(|&(c, kind)| match kind {
+            WatchKind::Read if c == cell && !write => Some(kind),
+            WatchKind::Write if c == cell && write => Some(kind),
+            WatchKind::WriteValue(val) if c == cell && write && val == new => Some(kind),
+            _ => None,
+        });
+        if let Some(kind) = kind {
+            self.watch_hit = Some(WatchHit {
+                cell,
+                kind,
+                code_pos: self.current_pos(),
+                step: self.steps,
+                old,
+                new,
+            });
+        }
+    }
+
+    // Stop with `ExecStatus::Paused` when the code pointer next reaches the given op
+    pub fn pause_at(&mut self, code_ptr: Option<usize>) {
+        self.pause_at = code_ptr;
+    }
+
+    pub fn current_op(&self) -> Option<Op> {
+        self.prog.ops.get(self.code_ptr).copied()
+    }
+
+    pub(super) fn breakpoint_hit(&self) -> Option<usize> {
+        if self.breakpoints.is_empty() || self.skip_breakpoint {
+            return None;
+        }
+
+        let idx = self.code_ptr;
+        let span = &self.prog.spans[idx];
+        // Simplified loops produce several ops with the same span, only the first of them can hit
+        if idx > 0 && self.prog.spans[idx - 1] == *span {
+            return None;
+        }
+        self.breakpoints.range(span.clone()).next().copied()
+    }
+
+    // Execute exactly one op, ignoring breakpoints
+    pub fn single_step(&mut self) -> Result<ExecStatus, Error> {
+        let pause_at = self.pause_at.take();
+        self.skip_breakpoint = true;
+        let status = self.advance(Some(1));
+        if keeps_pause(&status) {
+            self.pause_at = pause_at;
+        }
+        // Continuing from here shouldn't report a breakpoint on the op we stopped at
+        self.skip_breakpoint = true;
+        status
+    }
+
+    // Run an entire loop as if it were a single op. Anything other than the start of a loop is single-stepped.
+    pub fn step_over_loop(&mut self) -> Result<ExecStatus, Error> {
+        match self.current_op() {
+            Some(Op::Open(end)) => {
+                self.pause_at(Some(end));
+                self.skip_breakpoint = true;
+                self.advance(None)
+            },
+            _ => self.single_step(),
+        }
+    }
+
+    // Run until the innermost loop containing the code pointer exits
+    pub fn run_to_loop_exit(&mut self) -> Result<ExecStatus, Error> {
+        self.pause_at(self.enclosing_loop_end());
+        self.skip_breakpoint = true;
+        self.advance(None)
+    }
+
+    fn enclosing_loop_end(&self) -> Option<usize> {
+        self.prog.ops[..self.code_ptr.min(self.prog.len())]
+            .iter()
+            .rev()
+            .find_map(|op| match op {
+                Op::Open(end) if *end > self.code_ptr => Some(*end),
+                _ => None,
+            })
+    }
+}
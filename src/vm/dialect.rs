@@ -0,0 +1,154 @@
+use std::{
+    borrow::Cow,
+    cmp::Reverse,
+    ops::Range,
+};
+use super::VmError;
+
+// The BF commands, in the order their tokens are given to `Dialect::new`
+const COMMANDS: [char; 8] = ['>', '<', '+', '-', '.', ',', '[', ']'];
+
+const OOK: [&str; 8] = [
+    "Ook. Ook?",
+    "Ook? Ook.",
+    "Ook. Ook.",
+    "Ook! Ook!",
+    "Ook! Ook.",
+    "Ook. Ook!",
+    "Ook! Ook?",
+    "Ook? Ook!",
+];
+
+// A language that differs from BF only in the tokens used for each command. Whitespace inside a token matches any
+// run of whitespace, and tokens that start or end with a letter or digit only match whole words.
+#[derive(Clone, Debug)]
+pub struct Dialect {
+    tokens: [String; 8],
+    // Tokens are emitted separated by this
+    sep: String,
+}
+
+impl Dialect {
+    // Tokens are given in the order `><+-.,[]`
+    pub fn new(tokens: [&str; 8], sep: &str) -> Result<Self, VmError> {
+        let tokens = tokens.map(|token| token.split_whitespace().collect::<Vec<_>>().join(" "));
+        for (i, token) in tokens.iter().enumerate() {
+            if token.is_empty() {
+                return Err(VmError::InvalidDialect(format!("no token for '{}'", COMMANDS[i])));
+            } else if tokens[..i].contains(token) {
+                return Err(VmError::InvalidDialect(format!("token '{}' is used twice", token)));
+            }
+        }
+        Ok(Self {
+            tokens,
+            sep: sep.to_string(),
+        })
+    }
+
+    pub fn ook() -> Self {
+        Self::new(OOK, " ").unwrap()
+    }
+
+    // Parse a token map with one command per line, followed by its token, such as `+ inc`. Blank lines and lines
+    // starting with `#` are ignored. Tokens are emitted separated by spaces.
+    pub fn parse(map: &str) -> Result<Self, VmError> {
+        let mut tokens = [""; 8];
+        for line in map.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut chars = line.chars();
+            let cmd = chars.next().unwrap();
+            let idx = COMMANDS
+                .iter()
+                .position(|c| *c == cmd)
+                .ok_or_else(|| VmError::InvalidDialect(format!("'{}' is not a command", cmd)))?;
+            if !tokens[idx].is_empty() {
+                return Err(VmError::InvalidDialect(format!("'{}' is mapped twice", cmd)));
+            }
+            tokens[idx] = chars.as_str().trim();
+        }
+        Self::new(tokens, " ")
+    }
+
+    // Translate source in this dialect to BF. Anything that isn't a token is kept as a comment, except for BF
+    // commands, so debug characters still work.
+    pub fn to_bf<'a>(&self, code: &'a str) -> Cow<'a, str> {
+        self.translate(code).0
+    }
+
+    // Translate source like `to_bf`, also returning the range of source that each byte of the BF came from
+    pub fn translate<'a>(&self, code: &'a str) -> (Cow<'a, str>, Vec<Range<usize>>) {
+        if self.is_brainfuck() {
+            return (Cow::Borrowed(code), (0..code.len()).map(|pos| pos..pos + 1).collect());
+        }
+
+        // Longer tokens take priority over their prefixes
+        let mut order = (0..COMMANDS.len()).collect::<Vec<_>>();
+        order.sort_by_key(|idx| Reverse(self.tokens[*idx].len()));
+
+        let mut bf = String::new();
+        let mut map = Vec::new();
+        let mut pos = 0;
+        'tokens: while pos < code.len() {
+            for &idx in &order {
+                if let Some(end) = match_token(code, pos, &self.tokens[idx]) {
+                    bf.push(COMMANDS[idx]);
+                    map.push(pos..end);
+                    pos = end;
+                    continue 'tokens;
+                }
+            }
+            let c = code[pos..].chars().next().unwrap();
+            if !COMMANDS.contains(&c) {
+                bf.push(c);
+                map.extend((0..c.len_utf8()).map(|_| pos..pos + c.len_utf8()));
+            }
+            pos += c.len_utf8();
+        }
+        (Cow::Owned(bf), map)
+    }
+
+    // Translate BF to this dialect, dropping comments
+    pub fn emit(&self, bf: &str) -> String {
+        let tokens = bf
+            .chars()
+            .filter_map(|c| COMMANDS.iter().position(|cmd| *cmd == c))
+            .map(|idx| self.tokens[idx].as_str())
+            .collect::<Vec<_>>();
+        tokens.join(&self.sep)
+    }
+
+    fn is_brainfuck(&self) -> bool {
+        self.tokens.iter().zip(COMMANDS).all(|(token, cmd)| token.len() == 1 && token.starts_with(cmd))
+    }
+}
+
+// Returns the end of `token` if it appears at `pos`
+fn match_token(code: &str, pos: usize, token: &str) -> Option<usize> {
+    let is_word = |c: Option<char>| c.is_some_and(char::is_alphanumeric);
+    if is_word(token.chars().next()) && is_word(code[..pos].chars().next_back()) {
+        return None;
+    }
+
+    let mut end = pos;
+    for (i, word) in token.split(' ').enumerate() {
+        if i > 0 {
+            let rest = &code[end..];
+            let trimmed = rest.trim_start();
+            if trimmed.len() == rest.len() {
+                return None;
+            }
+            end += rest.len() - trimmed.len();
+        }
+        if !code[end..].starts_with(word) {
+            return None;
+        }
+        end += word.len();
+    }
+
+    if is_word(token.chars().next_back()) && is_word(code[end..].chars().next()) {
+        return None;
+    }
+    Some(end)
+}
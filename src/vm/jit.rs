@@ -0,0 +1,322 @@
+use std::{
+    ffi::c_void,
+    io::{
+        stdin,
+        stdout,
+        Read,
+        Write,
+    },
+    ptr,
+};
+use crate::{
+    backend::{
+        self,
+        x86::{
+            self,
+            Assembler,
+            Cond,
+            Label,
+            Reg,
+        },
+    },
+    Error,
+};
+use super::{
+    read_byte,
+    DebugHook,
+    EofPolicy,
+    Program,
+    Tape,
+    TapeMode,
+    Vm,
+    VmError,
+};
+
+extern "C" {
+    fn mmap(addr: *mut c_void, len: usize, prot: i32, flags: i32, fd: i32, offset: i64) -> *mut c_void;
+    fn mprotect(addr: *mut c_void, len: usize, prot: i32) -> i32;
+    fn munmap(addr: *mut c_void, len: usize) -> i32;
+}
+
+const PROT_READ: i32 = 0x1;
+const PROT_WRITE: i32 = 0x2;
+const PROT_EXEC: i32 = 0x4;
+const MAP_PRIVATE: i32 = 0x02;
+const MAP_ANONYMOUS: i32 = 0x20;
+
+// Values returned by generated code
+const STATUS_HALTED: u32 = 0;
+const STATUS_UNDERFLOW: u32 = 1;
+const STATUS_OVERFLOW: u32 = 2;
+// A callback failed and left an error in the context
+const STATUS_CALLBACK: u32 = 3;
+
+// Values returned by `jit_in`
+const IN_UNCHANGED: u32 = 0x100;
+const IN_IO_ERROR: u32 = 0x200;
+const IN_EOF_ERROR: u32 = 0x201;
+
+// Shared with generated code, which stores its registers in the first three fields before returning
+#[repr(C)]
+struct Context<'a> {
+    tape_ptr: i64,
+    steps: u64,
+    op: u64,
+    input: &'a mut dyn Read,
+    output: &'a mut dyn Write,
+    eof: EofPolicy,
+    error: Option<VmError>,
+    prog: &'a Program,
+    tape: *const Tape<u8>,
+    debug_hook: Option<Box<dyn DebugHook<u8>>>,
+}
+
+const CTX_TAPE_PTR: i32 = 0;
+const CTX_STEPS: i32 = 8;
+const CTX_OP: i32 = 16;
+
+type Entry = unsafe extern "sysv64" fn(*mut Context, *mut u8, u64, i64, u64) -> u32;
+
+extern "sysv64" fn jit_out(ctx: &mut Context, byte: u32, op: u64) -> u32 {
+    match ctx.output.write_all(&[byte as u8]) {
+        Ok(()) => 0,
+        Err(e) => {
+            // The op has completed, as in the interpreter
+            ctx.op = op + 1;
+            ctx.error = Some(VmError::Io(e));
+            1
+        },
+    }
+}
+
+extern "sysv64" fn jit_in(ctx: &mut Context, op: u64) -> u32 {
+    ctx.op = op;
+    match (read_byte(&mut ctx.input), ctx.eof) {
+        (Ok(Some(b)), _) => b as u32,
+        (Ok(None), EofPolicy::Zero) => 0,
+        (Ok(None), EofPolicy::MinusOne) => 0xFF,
+        (Ok(None), EofPolicy::Unchanged) => IN_UNCHANGED,
+        (Ok(None), EofPolicy::Error) => {
+            ctx.error = Some(VmError::UnexpectedEof {
+                code_pos: ctx.prog.spans[op as usize].start,
+            });
+            IN_EOF_ERROR
+        },
+        (Err(e), _) => {
+            ctx.error = Some(VmError::Io(e));
+            IN_IO_ERROR
+        },
+    }
+}
+
+extern "sysv64" fn jit_debug(ctx: &mut Context, tape_ptr: i64, op: u64) {
+    if let Some(hook) = &mut ctx.debug_hook {
+        // Safe to read: generated code doesn't touch the tape while a callback runs
+        let tape = unsafe { &*ctx.tape };
+        hook.debug(tape, tape_ptr as isize, ctx.prog.spans[op as usize].start);
+    }
+}
+
+struct JitIo {
+    // Taken when a callback reports an error
+    callback_failed: Label,
+    in_failed: Label,
+}
+
+impl x86::Io for JitIo {
+    fn input(&mut self, asm: &mut Assembler, op: usize) {
+        let skip = asm.new_label();
+        asm.mov(Reg::Rdi, Reg::R14);
+        asm.mov_imm32(Reg::Rsi, op as u32);
+        asm.mov_imm64(Reg::Rax, jit_in as *const () as u64);
+        asm.call(Reg::Rax);
+        asm.cmp_imm32(Reg::Rax, IN_UNCHANGED);
+        asm.jcc(Cond::A, self.in_failed);
+        asm.jcc(Cond::E, skip);
+        asm.store_byte(Reg::Rbx, Reg::R12, Reg::Rax);
+        asm.bind(skip);
+        asm.inc(Reg::R13);
+    }
+
+    fn output(&mut self, asm: &mut Assembler, op: usize) {
+        asm.mov(Reg::Rdi, Reg::R14);
+        asm.load_byte(Reg::Rsi, Reg::Rbx, Reg::R12);
+        asm.mov_imm32(Reg::Rdx, op as u32);
+        asm.mov_imm64(Reg::Rax, jit_out as *const () as u64);
+        asm.call(Reg::Rax);
+        asm.test(Reg::Rax, Reg::Rax);
+        asm.jcc(Cond::Ne, self.callback_failed);
+    }
+
+    fn debug(&mut self, asm: &mut Assembler, op: usize) {
+        asm.mov(Reg::Rdi, Reg::R14);
+        asm.mov(Reg::Rsi, Reg::R12);
+        asm.mov_imm32(Reg::Rdx, op as u32);
+        asm.mov_imm64(Reg::Rax, jit_debug as *const () as u64);
+        asm.call(Reg::Rax);
+    }
+}
+
+// A region of executable memory
+struct ExecMem {
+    addr: *mut c_void,
+    len: usize,
+}
+
+impl ExecMem {
+    fn new(code: &[u8]) -> Result<Self, VmError> {
+        let len = code.len().max(1);
+        unsafe {
+            let addr = mmap(ptr::null_mut(), len, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+            if addr as isize == -1 {
+                return Err(VmError::Io(std::io::Error::last_os_error()));
+            }
+            let mem = Self { addr, len };
+            ptr::copy_nonoverlapping(code.as_ptr(), addr as *mut u8, code.len());
+            if mprotect(addr, len, PROT_READ | PROT_EXEC) != 0 {
+                return Err(VmError::Io(std::io::Error::last_os_error()));
+            }
+            Ok(mem)
+        }
+    }
+}
+
+impl Drop for ExecMem {
+    fn drop(&mut self) {
+        unsafe {
+            munmap(self.addr, self.len);
+        }
+    }
+}
+
+// A program translated to native x86-64 code. Programs run on a VM's tape with the same results as the interpreter,
+// but breakpoints, watchpoints and profiling are ignored.
+pub struct JitProgram {
+    prog: Program,
+    mem: ExecMem,
+}
+
+// The generated code only touches its own memory and the tape it's given
+unsafe impl Send for JitProgram {}
+unsafe impl Sync for JitProgram {}
+
+impl JitProgram {
+    pub fn compile(prog: &Program) -> Result<Self, VmError> {
+        use Reg::*;
+
+        let mut asm = Assembler::new();
+        let exit = asm.new_label();
+        let ptr_error = asm.new_label();
+        let mut io = JitIo {
+            callback_failed: asm.new_label(),
+            in_failed: asm.new_label(),
+        };
+
+        for reg in [Rbx, R12, R13, R14, R15] {
+            asm.push(reg);
+        }
+        asm.mov(R14, Rdi);
+        asm.mov(Rbx, Rsi);
+        asm.mov(R15, Rdx);
+        asm.mov(R12, Rcx);
+        asm.mov(R13, R8);
+
+        x86::lower(prog, &mut asm, &mut io, ptr_error)
+            .map_err(|err| VmError::Unsupported(format!("op {} can't be encoded", err.0)))?;
+        asm.mov_imm32(Rax, STATUS_HALTED);
+        asm.jmp(exit);
+
+        // Distinguish underflow from overflow by the sign of the attempted pointer
+        asm.bind(ptr_error);
+        asm.store(R14, CTX_OP, Rcx);
+        asm.test(Rax, Rax);
+        asm.mov_imm32(Rax, STATUS_OVERFLOW);
+        asm.jcc(Cond::Ns, exit);
+        asm.mov_imm32(Rax, STATUS_UNDERFLOW);
+        asm.jmp(exit);
+
+        // A failed read only counts as a step if it got as far as reporting EOF
+        let failed = asm.new_label();
+        asm.bind(io.in_failed);
+        asm.cmp_imm32(Rax, IN_EOF_ERROR);
+        asm.jcc(Cond::Ne, failed);
+        asm.inc(R13);
+        asm.bind(io.callback_failed);
+        asm.bind(failed);
+        asm.mov_imm32(Rax, STATUS_CALLBACK);
+
+        asm.bind(exit);
+        asm.store(R14, CTX_TAPE_PTR, R12);
+        asm.store(R14, CTX_STEPS, R13);
+        for reg in [R15, R14, R13, R12, Rbx] {
+            asm.pop(reg);
+        }
+        asm.ret();
+
+        Ok(Self {
+            prog: prog.clone(),
+            mem: ExecMem::new(&asm.finish())?,
+        })
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.prog
+    }
+}
+
+impl Vm<u8> {
+    pub fn exec_jit(&mut self, code: &str) -> Result<(), Error> {
+        self.exec_jit_with(code, &mut stdin().lock(), &mut stdout().lock())
+    }
+
+    pub fn exec_jit_with(&mut self, code: &str, input: &mut impl Read, output: &mut impl Write) -> Result<(), Error> {
+        let prog = Program::compile_for(code, &self.config).map_err(Error::VmError)?;
+        let jit = JitProgram::compile(&prog).map_err(Error::VmError)?;
+        self.run_jit_with(&jit, input, output)
+    }
+
+    // Equivalent to `Vm::run_with`. Only fixed tapes with wrapping cells and strict pointer checks are supported.
+    pub fn run_jit_with(&mut self, jit: &JitProgram, input: &mut impl Read, output: &mut impl Write) -> Result<(), Error> {
+        backend::check_config(&self.config, "the JIT", true).map_err(Error::VmError)?;
+        // Restoring a snapshot can replace the tape with one that doesn't match the config
+        if self.tape.mode() != TapeMode::Fixed || self.tape.start() != 0 || self.tape.cells().is_empty() {
+            return Err(Error::VmError(VmError::Unsupported("the JIT requires a fixed, non-empty tape".to_string())));
+        }
+
+        self.load(&jit.prog)?;
+        let tape: *mut Tape<u8> = &mut self.tape;
+        let (cells, len) = unsafe { ((*tape).cells_mut().as_mut_ptr(), (*tape).cells().len()) };
+        let mut ctx = Context {
+            tape_ptr: 0,
+            steps: 0,
+            op: 0,
+            input,
+            output,
+            eof: self.config.eof,
+            error: None,
+            prog: &jit.prog,
+            tape,
+            debug_hook: self.debug_hook.take(),
+        };
+
+        let status = unsafe {
+            let entry: Entry = std::mem::transmute(jit.mem.addr);
+            entry(&mut ctx, cells, len as u64, self.tape_ptr as i64, self.steps)
+        };
+
+        let (tape_ptr, steps, op, error) = (ctx.tape_ptr, ctx.steps, ctx.op, ctx.error);
+        self.debug_hook = ctx.debug_hook;
+        self.tape_ptr = tape_ptr as isize;
+        self.steps = steps;
+        self.code_ptr = if status == STATUS_HALTED { jit.prog.len() } else { op as usize };
+
+        let code_pos = || self.current_pos();
+        match status {
+            STATUS_HALTED => Ok(()),
+            STATUS_UNDERFLOW => Err(VmError::PointerUnderflow { code_pos: code_pos(), step: steps }),
+            STATUS_OVERFLOW => Err(VmError::PointerOverflow { code_pos: code_pos(), step: steps }),
+            _ => Err(error.unwrap()),
+        }
+        .map_err(Error::VmError)
+    }
+}
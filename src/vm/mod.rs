@@ -1,108 +1,623 @@
-use std::io::{
-    stdin,
-    stdout,
-    Read,
-    Write,
+use std::{
+    collections::BTreeSet,
+    fmt,
+    io::{
+        self,
+        stdin,
+        stdout,
+        Read,
+        Write,
+    },
 };
 use crate::Error;
 
+pub mod batch;
+pub mod cell;
+pub mod config;
+pub mod debug;
+pub mod dialect;
+#[cfg(all(feature = "jit", target_arch = "x86_64", target_os = "linux"))]
+pub mod jit;
+pub mod profile;
+pub mod program;
+pub mod replay;
+pub mod snapshot;
+pub mod source;
+pub mod tape;
+
+pub use self::{
+    batch::{
+        Batch,
+        Case,
+        CaseResult,
+        Outcome,
+        Report,
+    },
+    cell::Cell,
+    config::{
+        EofPolicy,
+        Overflow,
+        TapeMode,
+        VmConfig,
+    },
+    debug::{
+        DebugHook,
+        TapeDump,
+        WatchHit,
+        WatchKind,
+    },
+    dialect::Dialect,
+    profile::{
+        LoopProfile,
+        Profile,
+    },
+    program::{
+        Op,
+        Program,
+    },
+    replay::Recording,
+    source::{
+        SourceLoc,
+        Unmatched,
+    },
+    tape::Tape,
+};
+#[cfg(all(feature = "jit", target_arch = "x86_64", target_os = "linux"))]
+pub use self::jit::JitProgram;
+
 #[derive(Debug)]
 pub enum VmError {
-    Unmatched(char),
+    UnmatchedBrackets(Vec<Unmatched>),
+    Io(io::Error),
+    InvalidSnapshot(String),
+    InvalidRecording(String),
+    InvalidDialect(String),
+    Unsupported(String),
+    // The program was compiled for a config that folds loops differently, see `Program::compile_for`
+    IncompatibleProgram,
+    ReplayDiverged {
+        step: u64,
+        reason: String,
+    },
+    CellOverflow {
+        ptr: isize,
+        code_pos: usize,
+    },
+    UnexpectedEof {
+        code_pos: usize,
+    },
+    PointerUnderflow {
+        code_pos: usize,
+        step: u64,
+    },
+    PointerOverflow {
+        code_pos: usize,
+        step: u64,
+    },
 }
 
-pub struct Vm {
-    tape: [u8; 10000],
+impl VmError {
+    // The source position of the op that failed, for errors raised while running a program
+    pub fn code_pos(&self) -> Option<usize> {
+        match self {
+            VmError::CellOverflow { code_pos, .. }
+            | VmError::UnexpectedEof { code_pos }
+            | VmError::PointerUnderflow { code_pos, .. }
+            | VmError::PointerOverflow { code_pos, .. } => Some(*code_pos),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VmError::UnmatchedBrackets(unmatched) => {
+                for (i, u) in unmatched.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{}", u)?;
+                }
+                Ok(())
+            },
+            VmError::Io(err) => write!(f, "I/O error: {}", err),
+            VmError::InvalidSnapshot(msg) => write!(f, "invalid snapshot: {}", msg),
+            VmError::InvalidRecording(msg) => write!(f, "invalid recording: {}", msg),
+            VmError::InvalidDialect(msg) => write!(f, "invalid dialect: {}", msg),
+            VmError::Unsupported(msg) => write!(f, "not supported: {}", msg),
+            VmError::IncompatibleProgram =>
+                write!(f, "program was compiled for a VM with different overflow or pointer settings"),
+            VmError::ReplayDiverged { step, reason } => write!(f, "replay diverged at step {}: {}", step, reason),
+            VmError::CellOverflow { ptr, code_pos } =>
+                write!(f, "cell {} overflowed at offset {}", ptr, code_pos),
+            VmError::UnexpectedEof { code_pos } => write!(f, "unexpected end of input at offset {}", code_pos),
+            VmError::PointerUnderflow { code_pos, step } =>
+                write!(f, "tape pointer moved below the start of the tape at offset {} (step {})", code_pos, step),
+            VmError::PointerOverflow { code_pos, step } =>
+                write!(f, "tape pointer moved past the end of the tape at offset {} (step {})", code_pos, step),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ExecStatus {
+    // The end of the program was reached
+    Halted,
+    // The fuel ran out, execution can be continued with `Vm::resume`
+    Suspended,
+    // The program wrote a byte
+    Output(u8),
+    // The program is waiting for `Vm::provide_input` or `Vm::provide_eof`
+    NeedsInput,
+    // A breakpoint was hit at the given source position, before executing the op there
+    Breakpoint(usize),
+    // The position requested by `Vm::pause_at` was reached
+    Paused,
+    // A watchpoint was triggered by the op just executed
+    Watchpoint(WatchHit),
+}
+
+pub struct Vm<C: Cell = u8> {
+    config: VmConfig,
+    tape: Tape<C>,
+    prog: Program,
+    tape_ptr: isize,
+    code_ptr: usize,
+    steps: u64,
+    // Input provided for the next `,`, where `Some(None)` signals the end of input
+    input: Option<Option<u8>>,
+    breakpoints: BTreeSet<usize>,
+    // Set when stopped on a breakpoint so that continuing doesn't immediately hit it again
+    skip_breakpoint: bool,
+    pause_at: Option<usize>,
+    watchpoints: Vec<(isize, WatchKind)>,
+    // A watchpoint triggered by the last op, reported by the next call to `advance`
+    watch_hit: Option<WatchHit>,
+    profile: Option<Profile>,
+    debug_hook: Option<Box<dyn DebugHook<C>>>,
+}
+
+impl<C: Cell> Default for Vm<C> {
+    fn default() -> Self {
+        Self::with_config(VmConfig::default())
+    }
 }
 
+// The default type parameter doesn't take part in inference, so these are only defined for 8-bit cells to keep
+// `Vm::new()` working without annotations. Use `Vm::with_config` or `Vm::default` for other cell types.
 impl Vm {
     pub fn new() -> Self {
+        Self::with_config(VmConfig::default())
+    }
+
+    pub fn compile(code: &str) -> Result<Program, VmError> {
+        Program::compile(code)
+    }
+}
+
+impl<C: Cell> Vm<C> {
+    // If the config has debug characters, they print the start of the tape until another hook is set
+    pub fn with_config(config: VmConfig) -> Self {
+        let debug_hook: Option<Box<dyn DebugHook<C>>> = if config.debug_chars.is_empty() {
+            None
+        } else {
+            Some(Box::new(TapeDump(20)))
+        };
         Self {
-            tape: [0; 10000],
+            tape: Tape::new(config.tape_len, config.tape_mode),
+            config,
+            prog: Program::default(),
+            tape_ptr: 0,
+            code_ptr: 0,
+            steps: 0,
+            input: None,
+            breakpoints: BTreeSet::new(),
+            skip_breakpoint: false,
+            pause_at: None,
+            watchpoints: Vec::new(),
+            watch_hit: None,
+            profile: None,
+            debug_hook,
+        }
+    }
+
+    pub fn tape(&self) -> &Tape<C> {
+        &self.tape
+    }
+
+    pub fn get(&self, offset: usize) -> C {
+        self.tape.get(offset as isize)
+    }
+
+    pub fn set(&mut self, offset: usize, val: C) {
+        if let Some(b) = self.tape.get_mut(offset as isize) {
+            *b = val;
+        }
+    }
+
+    pub fn incr(&mut self, offset: usize, incr: C) {
+        if let Some(b) = self.tape.get_mut(offset as isize) {
+            *b = b.wrapping_add(incr);
+        }
+    }
+
+    pub fn decr(&mut self, offset: usize, decr: C) {
+        if let Some(b) = self.tape.get_mut(offset as isize) {
+            *b = b.wrapping_sub(decr);
+        }
+    }
+
+    // Tape accesses made by the program, which are checked against watchpoints
+    fn get_at(&mut self, pos: isize) -> C {
+        let val = self.tape.get(pos);
+        if !self.watchpoints.is_empty() {
+            self.check_watch(pos, false, val, val);
+        }
+        val
+    }
+
+    fn set_at(&mut self, pos: isize, val: C) {
+        if let Some(b) = self.tape.get_mut(pos) {
+            let old = *b;
+            *b = val;
+            if !self.watchpoints.is_empty() {
+                self.check_watch(pos, true, old, val);
+            }
         }
     }
 
-    pub fn get(&self, offset: usize) -> u8 {
-        self.tape.get(offset).cloned().unwrap_or(0)
+    pub fn config(&self) -> &VmConfig {
+        &self.config
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.prog
+    }
+
+    pub fn tape_ptr(&self) -> isize {
+        self.tape_ptr
+    }
+
+    // The index of the next op to be executed
+    pub fn code_ptr(&self) -> usize {
+        self.code_ptr
+    }
+
+    // The source position of the next op to be executed
+    pub fn code_pos(&self) -> Option<usize> {
+        self.prog.span(self.code_ptr).map(|span| span.start)
     }
 
-    pub fn set(&mut self, offset: usize, val: u8) {
-        self.tape.get_mut(offset).map(|b| *b = val);
+    pub fn steps(&self) -> u64 {
+        self.steps
     }
 
-    pub fn incr(&mut self, offset: usize, incr: u8) {
-        self.tape.get_mut(offset).map(|b| *b = b.wrapping_add(incr));
+    pub fn is_halted(&self) -> bool {
+        self.code_ptr >= self.prog.len()
     }
 
-    pub fn decr(&mut self, offset: usize, decr: u8) {
-        self.tape.get_mut(offset).map(|b| *b = b.wrapping_sub(decr));
+    fn record_iters(&mut self, loop_idx: usize, n: u64) {
+        if let Some(profile) = &mut self.profile {
+            profile.record_iters(loop_idx, n);
+        }
+    }
+
+    fn current_pos(&self) -> usize {
+        self.prog.spans[self.code_ptr].start
+    }
+
+    fn add_at(&mut self, pos: isize, n: i64) -> Result<(), Error> {
+        let overflow = self.config.overflow;
+        let code_pos = self.current_pos();
+        if let Some(b) = self.tape.get_mut(pos) {
+            let old = *b;
+            *b = b.add_signed(n, overflow).ok_or(Error::VmError(VmError::CellOverflow {
+                ptr: pos,
+                code_pos,
+            }))?;
+            let new = *b;
+            if !self.watchpoints.is_empty() {
+                self.check_watch(pos, true, old, new);
+            }
+        }
+        Ok(())
+    }
+
+    fn scan(&mut self, stride: isize) -> Result<(), Error> {
+        let mut moves = 0;
+        while !self.get_at(self.tape_ptr).is_zero() {
+            self.tape_ptr = self.offset_ptr(self.tape_ptr, stride)?;
+            moves += 1;
+        }
+        self.record_iters(self.code_ptr, moves);
+        Ok(())
+    }
+
+    fn offset_ptr(&self, ptr: isize, n: isize) -> Result<isize, Error> {
+        let new_ptr = ptr.saturating_add(n);
+        let in_bounds = match self.tape.mode() {
+            TapeMode::Fixed => new_ptr >= 0 && new_ptr < self.tape.end(),
+            TapeMode::Growable => new_ptr >= 0,
+            TapeMode::Infinite => true,
+        };
+
+        if in_bounds {
+            Ok(new_ptr)
+        } else if self.config.lenient_pointer {
+            // Moving left of the first cell is saturated, cells past the end read as zero and ignore writes
+            Ok(new_ptr.max(0))
+        } else {
+            let code_pos = self.current_pos();
+            let step = self.steps;
+            Err(Error::VmError(if new_ptr < 0 {
+                VmError::PointerUnderflow { code_pos, step }
+            } else {
+                VmError::PointerOverflow { code_pos, step }
+            }))
+        }
     }
 
     pub fn exec(&mut self, code: &str) -> Result<(), Error> {
-        let code = code.bytes().collect::<Vec<_>>();
-
-        let mut tape_ptr = 0;
-        let mut code_ptr = 0;
-
-        while let Some(i) = code.get(code_ptr) {
-            match i {
-                b'.' => {
-                    stdout()
-                        .lock()
-                        .write(&[*self.tape
-                            .get(tape_ptr)
-                            .unwrap_or(&0)
-                        ])
-                        .unwrap();
-                },
-                b',' => {
-                    self.tape
-                        .get_mut(tape_ptr)
-                        .map(|b| *b = stdin()
-                            .lock()
-                            .bytes()
-                            .next()
-                            .unwrap_or(Ok(0))
-                            .unwrap_or(0)
-                        );
-                },
-                b'<' => tape_ptr = tape_ptr.saturating_sub(1),
-                b'>' => tape_ptr = tape_ptr.saturating_add(1),
-                b'+' => self.incr(tape_ptr, 1),
-                b'-' => self.decr(tape_ptr, 1),
-                b'[' if self.get(tape_ptr) == 0 => {
-                    let mut balance = 1;
-                    while balance != 0 {
-                        code_ptr += 1;
-                        match code.get(code_ptr) {
-                            Some(b'[') => balance += 1,
-                            Some(b']') => balance -= 1,
-                            Some(_) => {},
-                            None => return Err(Error::VmError(VmError::Unmatched('['))),
-                        }
-                    }
-                },
-                b']' if self.get(tape_ptr) != 0 => {
-                    let mut balance = 1;
-                    while balance != 0 {
-                        code_ptr -= 1;
-                        match code.get(code_ptr) {
-                            Some(b'[') => balance -= 1,
-                            Some(b']') => balance += 1,
-                            Some(_) => {},
-                            None => return Err(Error::VmError(VmError::Unmatched(']'))),
-                        }
-                    }
-                },
-                b':' => {
-                    self.tape[0..20].iter().for_each(|b| print!("{}, ", b));
-                    print!("\n");
+        self.exec_with(code, &mut stdin().lock(), &mut stdout().lock())
+    }
+
+    pub fn exec_with(&mut self, code: &str, input: &mut impl Read, output: &mut impl Write) -> Result<(), Error> {
+        let prog = Program::compile_for(code, &self.config).map_err(Error::VmError)?;
+        self.run_with(&prog, input, output)
+    }
+
+    pub fn run_to_vec(&mut self, code: &str, mut input: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut output = Vec::new();
+        self.exec_with(code, &mut input, &mut output)?;
+        Ok(output)
+    }
+
+    pub fn run(&mut self, prog: &Program) -> Result<(), Error> {
+        self.run_with(prog, &mut stdin().lock(), &mut stdout().lock())
+    }
+
+    pub fn run_with(&mut self, prog: &Program, input: &mut impl Read, output: &mut impl Write) -> Result<(), Error> {
+        self.load(prog)?;
+        self.execute(None, input, output).map(|_| ())
+    }
+
+    pub fn run_with_fuel(&mut self, prog: &Program, fuel: u64) -> Result<ExecStatus, Error> {
+        self.load(prog)?;
+        self.resume(fuel)
+    }
+
+    // Prepare to execute a program from the start, keeping the contents of the tape. Fails if the program was compiled
+    // for a config that this VM would run differently.
+    pub fn load(&mut self, prog: &Program) -> Result<(), Error> {
+        if !prog.is_compatible_with(&self.config) {
+            return Err(Error::VmError(VmError::IncompatibleProgram));
+        }
+        self.prog = prog.clone();
+        self.tape_ptr = 0;
+        self.code_ptr = 0;
+        self.steps = 0;
+        self.input = None;
+        self.skip_breakpoint = false;
+        self.pause_at = None;
+        self.watch_hit = None;
+        if self.config.profile {
+            self.profile = Some(Profile::new(prog));
+        }
+        Ok(())
+    }
+
+    // Available once a program has been loaded with `VmConfig::profile` enabled
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    pub fn resume(&mut self, fuel: u64) -> Result<ExecStatus, Error> {
+        self.resume_with(fuel, &mut stdin().lock(), &mut stdout().lock())
+    }
+
+    pub fn resume_with(&mut self, fuel: u64, input: &mut impl Read, output: &mut impl Write) -> Result<ExecStatus, Error> {
+        self.execute(Some(fuel), input, output)
+    }
+
+    fn execute(&mut self, fuel: Option<u64>, input: &mut impl Read, output: &mut impl Write) -> Result<ExecStatus, Error> {
+        let max_steps = fuel.map(|fuel| self.steps.saturating_add(fuel));
+        loop {
+            let fuel = max_steps.map(|max| max.saturating_sub(self.steps));
+            match self.advance(fuel)? {
+                ExecStatus::Output(b) => output
+                    .write_all(&[b])
+                    .map_err(|e| Error::VmError(VmError::Io(e)))?,
+                ExecStatus::NeedsInput => match read_byte(input).map_err(|e| Error::VmError(VmError::Io(e)))? {
+                    Some(b) => self.provide_input(b),
+                    None => self.provide_eof(),
                 },
-                _ => {},
+                status => return Ok(status),
             }
-            code_ptr += 1;
         }
+    }
+
+    pub fn provide_input(&mut self, b: u8) {
+        self.input = Some(Some(b));
+    }
 
+    pub fn provide_eof(&mut self) {
+        self.input = Some(None);
+    }
+
+    // Run until the program halts, runs out of fuel, produces output, needs input or stops for the debugger. Unlike
+    // `Vm::run`, this never blocks: the host is expected to handle each event and call this again.
+    pub fn advance(&mut self, fuel: Option<u64>) -> Result<ExecStatus, Error> {
+        let status = self.advance_to_event(fuel);
+        // A position passed to `pause_at` is kept across events that the host handles before carrying on, stopping for
+        // any other reason abandons it
+        if !keeps_pause(&status) {
+            self.pause_at = None;
+        }
+        status
+    }
+
+    fn advance_to_event(&mut self, fuel: Option<u64>) -> Result<ExecStatus, Error> {
+        if let Some(hit) = self.watch_hit.take() {
+            return Ok(ExecStatus::Watchpoint(hit));
+        }
+        let max_steps = fuel.map(|fuel| self.steps.saturating_add(fuel));
+        while !self.is_halted() {
+            if self.pause_at == Some(self.code_ptr) {
+                self.pause_at = None;
+                self.skip_breakpoint = true;
+                return Ok(ExecStatus::Paused);
+            }
+            if max_steps.is_some_and(|max| self.steps >= max) {
+                return Ok(ExecStatus::Suspended);
+            }
+            if let Some(code_pos) = self.breakpoint_hit() {
+                self.skip_breakpoint = true;
+                return Ok(ExecStatus::Breakpoint(code_pos));
+            }
+            if let Some(status) = self.step()? {
+                return Ok(status);
+            }
+            if let Some(hit) = self.watch_hit.take() {
+                return Ok(ExecStatus::Watchpoint(hit));
+            }
+        }
+        Ok(ExecStatus::Halted)
+    }
+
+    // Execute a single op. If it fails or needs input, the code pointer is left on the op.
+    fn step(&mut self) -> Result<Option<ExecStatus>, Error> {
+        let op = self.prog.ops[self.code_ptr];
+        // An empty fixed tape has no cell for the pointer to be on, so any access to the current cell is out of bounds
+        if self.tape.cells().is_empty()
+            && self.tape.mode() == TapeMode::Fixed
+            && !self.config.lenient_pointer
+            && !matches!(op, Op::Move(_) | Op::Debug)
+        {
+            return Err(Error::VmError(VmError::PointerOverflow {
+                code_pos: self.current_pos(),
+                step: self.steps + 1,
+            }));
+        }
+        if op == Op::In && self.input.is_none() {
+            return Ok(Some(ExecStatus::NeedsInput));
+        }
+        if !self.watchpoints.is_empty()
+            && matches!(op, Op::Clear | Op::MulAdd { .. } | Op::ScanLeft(_) | Op::ScanRight(_))
+            && self.prog.group(self.code_ptr).start == self.code_ptr
+        {
+            self.step_iteration()?;
+            return Ok(None);
+        }
+
+        let tape_ptr = self.tape_ptr;
+        let mut next = self.code_ptr + 1;
+        let mut status = None;
+        self.steps += 1;
+        if let Some(profile) = &mut self.profile {
+            profile.record_op(self.code_ptr);
+        }
+        match op {
+            Op::Add(n) => self.add_at(tape_ptr, n as i64)?,
+            Op::Move(n) => self.tape_ptr = self.offset_ptr(tape_ptr, n)?,
+            Op::Clear => {
+                if self.profile.is_some() {
+                    // The loop ran once for each decrement of its counter, which multiply loops leave until last
+                    let iters = self.tape.get(tape_ptr).to_i64() as u64;
+                    self.record_iters(self.prog.group(self.code_ptr).start, iters);
+                }
+                self.set_at(tape_ptr, C::default());
+            },
+            Op::MulAdd { offset, factor } => {
+                let val = self.get_at(tape_ptr);
+                if !val.is_zero() {
+                    let tgt = self.offset_ptr(tape_ptr, offset)?;
+                    self.add_at(tgt, val.to_i64() * factor as i64)?;
+                }
+            },
+            Op::ScanLeft(stride) => self.scan(-(stride as isize))?,
+            Op::ScanRight(stride) => self.scan(stride as isize)?,
+            Op::Out => status = Some(ExecStatus::Output(self.get_at(tape_ptr).to_byte())),
+            Op::In => {
+                match (self.input.take().unwrap(), self.config.eof) {
+                    (Some(b), _) => self.set_at(tape_ptr, C::from_byte(b)),
+                    (None, EofPolicy::Zero) => self.set_at(tape_ptr, C::default()),
+                    (None, EofPolicy::MinusOne) => {
+                        self.set_at(tape_ptr, C::default().add_signed(-1, Overflow::Wrapping).unwrap())
+                    },
+                    (None, EofPolicy::Unchanged) => {},
+                    (None, EofPolicy::Error) => return Err(Error::VmError(VmError::UnexpectedEof {
+                        code_pos: self.current_pos(),
+                    })),
+                }
+            },
+            Op::Open(end) if self.get_at(tape_ptr).is_zero() => next = end,
+            Op::Close(start) if !self.get_at(tape_ptr).is_zero() => {
+                next = start;
+                self.record_iters(start - 1, 1);
+            },
+            Op::Open(_) => self.record_iters(self.code_ptr, 1),
+            Op::Close(_) => {},
+            Op::Debug => {
+                let code_pos = self.current_pos();
+                if let Some(hook) = &mut self.debug_hook {
+                    hook.debug(&self.tape, tape_ptr, code_pos);
+                }
+            },
+        }
+        self.code_ptr = next;
+        self.skip_breakpoint = false;
+        Ok(status)
+    }
+
+    // Run one iteration of the loop that a simplified group of ops was compiled from, so that watchpoints see every
+    // access the loop makes. The code pointer stays on the group until the loop exits, which then counts as executing
+    // the whole group so that step counts don't depend on watchpoints.
+    fn step_iteration(&mut self) -> Result<(), Error> {
+        let group = self.prog.group(self.code_ptr);
+        let tape_ptr = self.tape_ptr;
+        if self.get_at(tape_ptr).is_zero() {
+            self.steps += group.len() as u64;
+            if let Some(profile) = &mut self.profile {
+                group.clone().for_each(|idx| profile.record_op(idx));
+            }
+            self.code_ptr = group.end;
+            self.skip_breakpoint = false;
+            return Ok(());
+        }
+
+        match self.prog.ops[group.start] {
+            Op::ScanLeft(stride) => self.tape_ptr = self.offset_ptr(tape_ptr, -(stride as isize))?,
+            Op::ScanRight(stride) => self.tape_ptr = self.offset_ptr(tape_ptr, stride as isize)?,
+            _ => {
+                for idx in group.clone() {
+                    if let Op::MulAdd { offset, factor } = self.prog.ops[idx] {
+                        let tgt = self.offset_ptr(tape_ptr, offset)?;
+                        self.add_at(tgt, factor as i64)?;
+                    }
+                }
+                self.add_at(tape_ptr, -1)?;
+            },
+        }
+        self.record_iters(group.start, 1);
+        // Breakpoints on the loop were checked before its first iteration
+        self.skip_breakpoint = true;
         Ok(())
     }
 }
+
+fn keeps_pause(status: &Result<ExecStatus, Error>) -> bool {
+    matches!(status, Ok(ExecStatus::Output(_) | ExecStatus::NeedsInput | ExecStatus::Suspended))
+}
+
+fn read_byte(input: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut buf = [0];
+    loop {
+        match input.read(&mut buf) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(buf[0])),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+            Err(e) => return Err(e),
+        }
+    }
+}
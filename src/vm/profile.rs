@@ -0,0 +1,143 @@
+use std::{
+    cmp::Reverse,
+    fmt::Write,
+    ops::Range,
+};
+use super::{
+    Op,
+    Program,
+};
+
+#[derive(Clone, Debug)]
+pub struct Profile {
+    prog: Program,
+    // Number of times each op was executed
+    op_counts: Vec<u64>,
+    // Number of times the body of each loop was entered, indexed by its `Op::Open` or the first op of a simplified loop
+    loop_iters: Vec<u64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct LoopProfile {
+    pub span: Range<usize>,
+    pub iters: u64,
+    // Total ops executed inside the loop, including nested loops
+    pub steps: u64,
+}
+
+impl Profile {
+    pub fn new(prog: &Program) -> Self {
+        Self {
+            prog: prog.clone(),
+            op_counts: vec![0; prog.len()],
+            loop_iters: vec![0; prog.len()],
+        }
+    }
+
+    pub(super) fn record_op(&mut self, idx: usize) {
+        self.op_counts[idx] += 1;
+    }
+
+    pub(super) fn record_iters(&mut self, loop_idx: usize, n: u64) {
+        self.loop_iters[loop_idx] += n;
+    }
+
+    pub fn op_counts(&self) -> &[u64] {
+        &self.op_counts
+    }
+
+    pub fn total_steps(&self) -> u64 {
+        self.op_counts.iter().sum()
+    }
+
+    // All loops in the program, hottest first. Simplified loops are included, with the iterations they stand for.
+    pub fn loops(&self) -> Vec<LoopProfile> {
+        let mut loops = self.prog.ops
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, op)| match op {
+                Op::Open(end) => Some(LoopProfile {
+                    span: self.prog.spans[idx].start..self.prog.spans[end - 1].end,
+                    iters: self.loop_iters[idx],
+                    steps: self.op_counts[idx..*end].iter().sum(),
+                }),
+                Op::Clear | Op::MulAdd { .. } | Op::ScanLeft(_) | Op::ScanRight(_) => {
+                    let group = self.prog.group(idx);
+                    (group.start == idx).then(|| LoopProfile {
+                        span: self.prog.spans[idx].clone(),
+                        iters: self.loop_iters[idx],
+                        steps: self.op_counts[group].iter().sum(),
+                    })
+                },
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        loops.sort_by_key(|l| Reverse(l.steps));
+        loops
+    }
+
+    // Ops executed within each region of the source delimited by `BLOCK_HEAD(n)`, `BLOCK_CODE(n)` and `BLOCK_END(n)`
+    // annotations, as emitted by `bfir::Program::to_bf`. Ops before the first annotation are attributed to "<start>".
+    pub fn regions(&self, code: &str) -> Vec<(String, u64)> {
+        let mut markers = vec![(0, "<start>".to_string())];
+        for kind in &["BLOCK_HEAD(", "BLOCK_CODE(", "BLOCK_END("] {
+            for (pos, _) in code.match_indices(kind) {
+                if let Some(len) = code[pos..].find(')') {
+                    markers.push((pos, code[pos..pos + len + 1].to_string()));
+                }
+            }
+        }
+        markers.sort();
+
+        let mut regions = markers
+            .iter()
+            .map(|(_, name)| (name.clone(), 0))
+            .collect::<Vec<_>>();
+        for (span, count) in self.prog.spans.iter().zip(&self.op_counts) {
+            let region = markers
+                .iter()
+                .rposition(|(pos, _)| *pos <= span.start)
+                .unwrap_or(0);
+            regions[region].1 += count;
+        }
+        regions
+    }
+
+    pub fn report(&self, code: &str, top: usize) -> String {
+        let total = self.total_steps().max(1);
+        let percent = |n: u64| n as f64 * 100.0 / total as f64;
+        let mut s = String::new();
+
+        writeln!(s, "{} ops executed", self.total_steps()).unwrap();
+
+        writeln!(s, "\nhottest loops:").unwrap();
+        for l in self.loops().iter().take(top).filter(|l| l.steps > 0) {
+            let src = code[l.span.clone()].split_whitespace().collect::<String>();
+            let src = if src.chars().count() > 40 {
+                src.chars().take(37).chain("...".chars()).collect()
+            } else {
+                src
+            };
+            writeln!(
+                s,
+                "  {:>5.1}% {:>12} ops {:>10} iters  {:?}  {}",
+                percent(l.steps),
+                l.steps,
+                l.iters,
+                l.span,
+                src,
+            ).unwrap();
+        }
+
+        let mut regions = self.regions(code);
+        if regions.len() > 1 {
+            regions.sort_by_key(|(_, steps)| Reverse(*steps));
+            writeln!(s, "\nhottest blocks:").unwrap();
+            for (name, steps) in regions.iter().take(top).filter(|(_, steps)| *steps > 0) {
+                writeln!(s, "  {:>5.1}% {:>12} ops  {}", percent(*steps), steps, name).unwrap();
+            }
+        }
+
+        s
+    }
+}
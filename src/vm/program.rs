@@ -0,0 +1,300 @@
+use std::{
+    ops::Range,
+    sync::Arc,
+};
+use super::{
+    source::{
+        self,
+        Unmatched,
+    },
+    Overflow,
+    VmConfig,
+    VmError,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Op {
+    Add(i32),
+    Move(isize),
+    // Set the current cell to zero
+    Clear,
+    // Add the current cell, multiplied by `factor`, to the cell at `offset`
+    MulAdd {
+        offset: isize,
+        factor: i32,
+    },
+    // Move by the given stride until a zero cell is found
+    ScanLeft(usize),
+    ScanRight(usize),
+    Out,
+    In,
+    // Jump to the given op if the current cell is zero
+    Open(usize),
+    // Jump to the given op if the current cell is not zero
+    Close(usize),
+    // One of the configured debug characters, see `VmConfig::debug_chars`
+    Debug,
+}
+
+// Programs are immutable once compiled, so they can be cheaply shared between VMs
+#[derive(Clone, Debug)]
+pub struct Program {
+    pub(crate) ops: Arc<[Op]>,
+    // The range of source bytes that each op was generated from
+    pub(crate) spans: Arc<[Range<usize>]>,
+    // Whether multiply loops may have been folded, see `compile_for`
+    pub(crate) multiply: bool,
+}
+
+impl Default for Program {
+    fn default() -> Self {
+        Self {
+            ops: Arc::new([]),
+            spans: Arc::new([]),
+            multiply: false,
+        }
+    }
+}
+
+struct Builder {
+    ops: Vec<Op>,
+    spans: Vec<Range<usize>>,
+}
+
+impl Program {
+    // Compile for the default config, see `compile_for`
+    pub fn compile(code: &str) -> Result<Self, VmError> {
+        Self::compile_with(code, &[])
+    }
+
+    // Compile for the default config with the given characters treated as debug instructions rather than comments.
+    // Only ASCII characters that aren't already instructions can be used.
+    pub fn compile_with(code: &str, debug_chars: &[char]) -> Result<Self, VmError> {
+        Self::compile_for(code, &VmConfig::default().with_debug_chars(debug_chars))
+    }
+
+    // Compile source for a VM with the given config, translating it from the config's dialect first. Spans and error
+    // locations are mapped back to the source, so they always refer to `code`. Multiply loops are only folded with wrapping cells and strict
+    // pointer checks, where the result is the same as running the loop, so a program should only be run by VMs with
+    // the overflow and pointer settings it was compiled for, which `Vm::load` checks.
+    pub fn compile_for(code: &str, config: &VmConfig) -> Result<Self, VmError> {
+        let dialect = match &config.dialect {
+            Some(dialect) => dialect,
+            None => return Self::build(code, config),
+        };
+        let (bf, map) = dialect.translate(code);
+        let to_source = |span: &Range<usize>| map[span.start].start..map[span.end - 1].end;
+        match Self::build(&bf, config) {
+            Ok(prog) => Ok(Self {
+                spans: prog.spans.iter().map(to_source).collect(),
+                ..prog
+            }),
+            Err(VmError::UnmatchedBrackets(unmatched)) => Err(VmError::UnmatchedBrackets(
+                unmatched
+                    .into_iter()
+                    .map(|u| Unmatched::new(code, map[u.loc.offset].start, u.bracket))
+                    .collect(),
+            )),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn build(code: &str, config: &VmConfig) -> Result<Self, VmError> {
+        Self::validate(code)?;
+
+        let debug_chars = &config.debug_chars;
+        let multiply = can_fold_multiply(config);
+
+        let mut prog = Builder {
+            ops: Vec::new(),
+            spans: Vec::new(),
+        };
+        let mut open = Vec::new();
+
+        for (pos, c) in code.bytes().enumerate() {
+            match c {
+                b'+' => prog.push_add(pos, 1),
+                b'-' => prog.push_add(pos, -1),
+                b'>' => prog.push_move(pos, 1),
+                b'<' => prog.push_move(pos, -1),
+                b'.' => prog.push(pos, Op::Out),
+                b',' => prog.push(pos, Op::In),
+                b'[' => {
+                    open.push(prog.ops.len());
+                    // Patched when the matching ']' is found
+                    prog.push(pos, Op::Open(0));
+                },
+                b']' => {
+                    let start = open.pop().unwrap();
+                    let span = prog.spans[start].start..pos + 1;
+                    if let Some(simple) = simplify_loop(&prog.ops[start + 1..], multiply) {
+                        prog.ops.truncate(start);
+                        prog.spans.truncate(start);
+                        for op in simple {
+                            prog.ops.push(op);
+                            prog.spans.push(span.clone());
+                        }
+                    } else {
+                        prog.ops[start] = Op::Open(prog.ops.len() + 1);
+                        prog.push(pos, Op::Close(start + 1));
+                    }
+                },
+                c if c.is_ascii() && debug_chars.contains(&(c as char)) => prog.push(pos, Op::Debug),
+                _ => {},
+            }
+        }
+
+        Ok(Self {
+            ops: prog.ops.into(),
+            spans: prog.spans.into(),
+            multiply,
+        })
+    }
+
+    // Check the source for problems without compiling it, reporting every unmatched bracket at once
+    pub fn validate(code: &str) -> Result<(), VmError> {
+        let unmatched = source::unmatched_brackets(code);
+        if unmatched.is_empty() {
+            Ok(())
+        } else {
+            Err(VmError::UnmatchedBrackets(unmatched))
+        }
+    }
+
+    // Whether a VM with the given config runs this program the same way as its source
+    pub fn is_compatible_with(&self, config: &VmConfig) -> bool {
+        !self.multiply || can_fold_multiply(config)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    pub fn ops(&self) -> &[Op] {
+        &self.ops
+    }
+
+    pub fn span(&self, idx: usize) -> Option<Range<usize>> {
+        self.spans.get(idx).cloned()
+    }
+
+    // The ops that a simplified loop was compiled to all have the loop's span. Given any of them, this returns the
+    // whole group, which for other ops is just the op itself.
+    pub(crate) fn group(&self, idx: usize) -> Range<usize> {
+        let span = &self.spans[idx];
+        let start = self.spans[..idx].iter().rposition(|s| s != span).map_or(0, |i| i + 1);
+        let end = self.spans[idx..].iter().position(|s| s != span).map_or(self.len(), |i| idx + i);
+        start..end
+    }
+
+    // A hash of the ops (FNV-1a), used to check that a snapshot belongs to this program
+    pub fn fingerprint(&self) -> u64 {
+        let mut hash = 0xcbf29ce484222325u64;
+        for op in self.ops.iter() {
+            let (tag, a, b) = match *op {
+                Op::Add(n) => (0, n as i64, 0),
+                Op::Move(n) => (1, n as i64, 0),
+                Op::Clear => (2, 0, 0),
+                Op::MulAdd { offset, factor } => (3, offset as i64, factor as i64),
+                Op::ScanLeft(n) => (4, n as i64, 0),
+                Op::ScanRight(n) => (5, n as i64, 0),
+                Op::Out => (6, 0, 0),
+                Op::In => (7, 0, 0),
+                Op::Open(n) => (8, n as i64, 0),
+                Op::Close(n) => (9, n as i64, 0),
+                Op::Debug => (10, 0, 0),
+            };
+            for byte in [tag].iter().chain(&a.to_le_bytes()).chain(&b.to_le_bytes()) {
+                hash = (hash ^ *byte as u64).wrapping_mul(0x100000001b3);
+            }
+        }
+        hash
+    }
+
+}
+
+impl Builder {
+    fn push(&mut self, pos: usize, op: Op) {
+        self.ops.push(op);
+        self.spans.push(pos..pos + 1);
+    }
+
+    // Runs are only folded while they head in the same direction, so a folded run overflows or leaves the tape
+    // exactly when executing it one instruction at a time would, although the error is reported at the start of the
+    // run
+    fn push_add(&mut self, pos: usize, n: i32) {
+        match self.ops.last_mut() {
+            Some(Op::Add(m)) if m.signum() == n.signum() => {
+                *m += n;
+                self.spans.last_mut().unwrap().end = pos + 1;
+            },
+            _ => self.push(pos, Op::Add(n)),
+        }
+    }
+
+    fn push_move(&mut self, pos: usize, n: isize) {
+        match self.ops.last_mut() {
+            Some(Op::Move(m)) if m.signum() == n.signum() => {
+                *m += n;
+                self.spans.last_mut().unwrap().end = pos + 1;
+            },
+            _ => self.push(pos, Op::Move(n)),
+        }
+    }
+}
+
+fn can_fold_multiply(config: &VmConfig) -> bool {
+    config.overflow == Overflow::Wrapping && !config.lenient_pointer
+}
+
+// Attempt to replace the body of a loop with straight-line ops
+fn simplify_loop(body: &[Op], multiply: bool) -> Option<Vec<Op>> {
+    match body {
+        [Op::Add(-1)] => return Some(vec![Op::Clear]),
+        [Op::Move(n)] if *n > 0 => return Some(vec![Op::ScanRight(*n as usize)]),
+        [Op::Move(n)] if *n < 0 => return Some(vec![Op::ScanLeft(n.unsigned_abs())]),
+        _ if !multiply => return None,
+        _ => {},
+    }
+
+    // Multiply loops: only adds and moves, returning to the starting cell, with the counter decremented once per
+    // iteration
+    let mut offset = 0;
+    let mut visited = (0, 0);
+    let mut deltas: Vec<(isize, i32)> = Vec::new();
+    for op in body {
+        match op {
+            Op::Move(n) => {
+                offset += n;
+                visited = (visited.0.min(offset), visited.1.max(offset));
+            },
+            Op::Add(n) => match deltas.iter_mut().find(|(o, _)| *o == offset) {
+                Some((_, d)) => *d += n,
+                None => deltas.push((offset, *n)),
+            },
+            _ => return None,
+        }
+    }
+
+    if offset != 0 || deltas.iter().find(|(o, _)| *o == 0).map(|(_, d)| *d) != Some(-1) {
+        return None;
+    }
+
+    // `Op::MulAdd` only checks the cells it adds to, so the body mustn't pass through any cells beyond them
+    let targets = deltas.iter().filter(|(_, d)| *d != 0).map(|(o, _)| *o);
+    if targets.clone().min() > Some(visited.0) || targets.max() < Some(visited.1) {
+        return None;
+    }
+
+    let mut ops = deltas
+        .into_iter()
+        .filter(|(o, d)| *o != 0 && *d != 0)
+        .map(|(offset, factor)| Op::MulAdd { offset, factor })
+        .collect::<Vec<_>>();
+    ops.push(Op::Clear);
+    Some(ops)
+}
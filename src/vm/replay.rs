@@ -0,0 +1,186 @@
+use std::{
+    fmt::Write as _,
+    fs,
+    io::{
+        Read,
+        Write,
+    },
+    path::Path,
+};
+use super::{
+    read_byte,
+    Cell,
+    ExecStatus,
+    Program,
+    Vm,
+    VmError,
+};
+use crate::Error;
+
+// Everything a program read and wrote during a run, enough to reproduce it deterministically
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Recording {
+    // The step at which each `,` was executed and the byte it consumed, `None` for end of input
+    pub inputs: Vec<(u64, Option<u8>)>,
+    pub output: Vec<u8>,
+}
+
+// Log format
+// ----------
+// One entry per line. Input and output entries may be interleaved, each kind is in the order it happened:
+// in <step> <byte>
+// eof <step>
+// out <hex bytes>
+
+impl Recording {
+    pub fn to_log(&self) -> String {
+        let mut s = String::new();
+        for (step, b) in &self.inputs {
+            s += &input_entry(*step, *b);
+        }
+        s += &output_entries(&self.output);
+        s
+    }
+
+    pub fn from_log(log: &str) -> Result<Self, Error> {
+        let mut rec = Self::default();
+        for (i, line) in log.lines().enumerate() {
+            let err = || Error::VmError(VmError::InvalidRecording(format!("line {}: {:?}", i + 1, line)));
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next(), words.next()) {
+                (Some("in"), Some(step), Some(b)) => rec.inputs.push((
+                    step.parse().map_err(|_| err())?,
+                    Some(b.parse().map_err(|_| err())?),
+                )),
+                (Some("eof"), Some(step), None) => rec.inputs.push((step.parse().map_err(|_| err())?, None)),
+                (Some("out"), Some(hex), None) if hex.len() % 2 == 0 => for i in (0..hex.len()).step_by(2) {
+                    let b = hex.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()).ok_or_else(err)?;
+                    rec.output.push(b);
+                },
+                (None, _, _) => {},
+                _ => return Err(err()),
+            }
+        }
+        Ok(rec)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        fs::write(path, self.to_log()).map_err(|e| Error::VmError(VmError::Io(e)))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let log = fs::read_to_string(path).map_err(|e| Error::VmError(VmError::Io(e)))?;
+        Self::from_log(&log)
+    }
+}
+
+fn input_entry(step: u64, b: Option<u8>) -> String {
+    match b {
+        Some(b) => format!("in {} {}\n", step, b),
+        None => format!("eof {}\n", step),
+    }
+}
+
+fn output_entries(output: &[u8]) -> String {
+    let mut s = String::new();
+    for chunk in output.chunks(32) {
+        s += "out ";
+        chunk.iter().for_each(|b| write!(s, "{:02x}", b).unwrap());
+        s += "\n";
+    }
+    s
+}
+
+impl<C: Cell> Vm<C> {
+    // Run a program like `Vm::run_with`, logging its input and output. The log is written as the program runs, each
+    // input entry as soon as it's consumed, so a run that fails still leaves a log that replays up to the failure.
+    pub fn run_recorded(
+        &mut self,
+        prog: &Program,
+        input: &mut impl Read,
+        output: &mut impl Write,
+        log: &mut impl Write,
+    ) -> Result<Recording, Error> {
+        let mut rec = Recording::default();
+        // Output is logged in chunks, this much of it has been written so far
+        let mut logged = 0;
+        self.load(prog)?;
+        let result = loop {
+            match self.advance(None) {
+                Ok(ExecStatus::Output(b)) => {
+                    rec.output.push(b);
+                    if let Err(e) = output.write_all(&[b]) {
+                        break Err(Error::VmError(VmError::Io(e)));
+                    }
+                },
+                Ok(ExecStatus::NeedsInput) => {
+                    let b = match read_byte(input) {
+                        Ok(b) => b,
+                        Err(e) => break Err(Error::VmError(VmError::Io(e))),
+                    };
+                    rec.inputs.push((self.steps, b));
+                    let entry = output_entries(&rec.output[logged..]) + &input_entry(self.steps, b);
+                    logged = rec.output.len();
+                    if let Err(e) = log.write_all(entry.as_bytes()).and_then(|_| log.flush()) {
+                        break Err(Error::VmError(VmError::Io(e)));
+                    }
+                    match b {
+                        Some(b) => self.provide_input(b),
+                        None => self.provide_eof(),
+                    }
+                },
+                Ok(ExecStatus::Halted) => break Ok(()),
+                // Only reachable when debugging, so just keep going
+                Ok(_) => {},
+                Err(err) => break Err(err),
+            }
+        };
+
+        log.write_all(output_entries(&rec.output[logged..]).as_bytes())
+            .and_then(|_| log.flush())
+            .map_err(|e| Error::VmError(VmError::Io(e)))?;
+        result.map(|_| rec)
+    }
+
+    // Run a program, feeding it the recorded input and failing with `VmError::ReplayDiverged` as soon as its behaviour
+    // differs from the recording
+    pub fn replay(&mut self, prog: &Program, rec: &Recording, output: &mut impl Write) -> Result<(), Error> {
+        let mut inputs = rec.inputs.iter();
+        let mut expected_output = rec.output.iter();
+        self.load(prog)?;
+        loop {
+            let diverged = |vm: &Self, reason: String| Error::VmError(VmError::ReplayDiverged {
+                step: vm.steps,
+                reason,
+            });
+            match self.advance(None)? {
+                ExecStatus::Output(b) => {
+                    output.write_all(&[b]).map_err(|e| Error::VmError(VmError::Io(e)))?;
+                    match expected_output.next() {
+                        Some(expected) if *expected == b => {},
+                        Some(expected) => return Err(diverged(self, format!("wrote {}, expected {}", b, expected))),
+                        None => return Err(diverged(self, format!("wrote {} after the end of the recorded output", b))),
+                    }
+                },
+                ExecStatus::NeedsInput => match inputs.next() {
+                    Some((step, b)) if *step == self.steps => match b {
+                        Some(b) => self.provide_input(*b),
+                        None => self.provide_eof(),
+                    },
+                    Some((step, _)) => {
+                        return Err(diverged(self, format!("read input, but the recording read at step {}", step)));
+                    },
+                    None => return Err(diverged(self, "read input after the end of the recorded input".to_string())),
+                },
+                ExecStatus::Halted => {
+                    return match (inputs.next(), expected_output.len()) {
+                        (None, 0) => Ok(()),
+                        (Some((step, _)), _) => Err(diverged(self, format!("halted, but the recording read at step {}", step))),
+                        (None, n) => Err(diverged(self, format!("halted with {} recorded output bytes missing", n))),
+                    };
+                },
+                _ => {},
+            }
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use std::{
+    convert::TryFrom,
+    mem,
+};
+use super::{
+    Cell,
+    Overflow,
+    Program,
+    Tape,
+    TapeMode,
+    Vm,
+    VmError,
+};
+use crate::Error;
+
+// Snapshot format
+// ---------------
+// All integers are LEB128 varints, signed ones zigzag-encoded first.
+//
+// "FVMS" version cell_width
+// program_fingerprint(u64) steps tape_ptr(signed) code_ptr input_state [input_byte]
+// tape_start(signed) tape_len
+// runs of (zero_cells, literal_cells, literal values...) until tape_len cells are covered
+
+const MAGIC: &[u8] = b"FVMS";
+const VERSION: u8 = 1;
+
+impl<C: Cell> Vm<C> {
+    // Capture the execution state of the VM. Configuration, breakpoints and the program itself are not included.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut buf = MAGIC.to_vec();
+        buf.push(VERSION);
+        buf.push(mem::size_of::<C>() as u8);
+
+        write_uint(&mut buf, self.prog.fingerprint());
+        write_uint(&mut buf, self.steps);
+        write_int(&mut buf, self.tape_ptr as i64);
+        write_uint(&mut buf, self.code_ptr as u64);
+        match self.input {
+            None => buf.push(0),
+            Some(None) => buf.push(1),
+            Some(Some(b)) => buf.extend_from_slice(&[2, b]),
+        }
+
+        let cells = self.tape.cells();
+        write_int(&mut buf, self.tape.start() as i64);
+        write_uint(&mut buf, cells.len() as u64);
+        let mut i = 0;
+        while i < cells.len() {
+            let zeros = cells[i..].iter().take_while(|c| c.is_zero()).count();
+            i += zeros;
+            let literals = cells[i..].iter().take_while(|c| !c.is_zero()).count();
+            write_uint(&mut buf, zeros as u64);
+            write_uint(&mut buf, literals as u64);
+            for cell in &cells[i..i + literals] {
+                write_uint(&mut buf, cell.to_i64() as u64);
+            }
+            i += literals;
+        }
+
+        buf
+    }
+
+    // Load a program and continue from a snapshot previously taken while running it
+    pub fn restore(&mut self, prog: &Program, snapshot: &[u8]) -> Result<(), Error> {
+        let err = |msg: &str| Error::VmError(VmError::InvalidSnapshot(msg.to_string()));
+        let mut r = Reader(snapshot);
+
+        if r.bytes(MAGIC.len()) != Some(MAGIC) || r.byte() != Some(VERSION) {
+            return Err(err("not a snapshot"));
+        }
+        if r.byte() != Some(mem::size_of::<C>() as u8) {
+            return Err(err("cell width does not match"));
+        }
+        if r.uint() != Some(prog.fingerprint()) {
+            return Err(err("snapshot was taken from a different program"));
+        }
+
+        let truncated = || err("truncated snapshot");
+        let steps = r.uint().ok_or_else(truncated)?;
+        let tape_ptr = r.int().ok_or_else(truncated)?;
+        let code_ptr = r.uint().ok_or_else(truncated)? as usize;
+        let input = match r.byte().ok_or_else(truncated)? {
+            0 => None,
+            1 => Some(None),
+            2 => Some(Some(r.byte().ok_or_else(truncated)?)),
+            _ => return Err(err("invalid input state")),
+        };
+
+        let start = r.int().ok_or_else(truncated)?;
+        let len = r.uint().ok_or_else(truncated)?;
+        let (origin, len) = match (start.checked_neg().map(usize::try_from), usize::try_from(len)) {
+            (Some(Ok(origin)), Ok(len)) if origin <= len => (origin, len),
+            _ => return Err(err("invalid tape bounds")),
+        };
+        if self.config.tape_mode == TapeMode::Fixed && (origin, len) != (0, self.config.tape_len) {
+            return Err(err("tape length does not match the config"));
+        }
+        if code_ptr > prog.len() {
+            return Err(err("invalid code pointer"));
+        }
+
+        // Lengths are untrusted, so the tape is only allocated as its runs are read
+        let mut cells = Vec::new();
+        while cells.len() < len {
+            let zeros = r.uint().ok_or_else(truncated)?;
+            let literals = r.uint().ok_or_else(truncated)?;
+            let end = usize::try_from(zeros)
+                .ok()
+                .zip(usize::try_from(literals).ok())
+                .and_then(|(zeros, literals)| cells.len().checked_add(zeros)?.checked_add(literals));
+            if end.is_none_or(|end| end > len) {
+                return Err(err("tape runs exceed tape length"));
+            }
+            // Every literal takes at least one byte
+            if literals > r.0.len() as u64 {
+                return Err(truncated());
+            }
+            if cells.try_reserve(end.unwrap() - cells.len()).is_err() {
+                return Err(err("tape is too large"));
+            }
+            cells.resize(cells.len() + zeros as usize, C::default());
+            for _ in 0..literals {
+                let val = r.uint().ok_or_else(truncated)? as i64;
+                cells.push(C::default().add_signed(val, Overflow::Wrapping).unwrap());
+            }
+        }
+
+        let tape = Tape::from_parts(cells, origin, self.config.tape_mode);
+        // The pointer must be somewhere the VM could have moved it to
+        let tape_ptr = isize::try_from(tape_ptr).map_err(|_| err("invalid tape pointer"))?;
+        let in_bounds = match self.config.tape_mode {
+            TapeMode::Fixed if !self.config.lenient_pointer => tape_ptr >= 0 && tape_ptr < tape.end(),
+            TapeMode::Fixed | TapeMode::Growable => tape_ptr >= 0,
+            TapeMode::Infinite => true,
+        };
+        if tape_ptr != 0 && !in_bounds {
+            return Err(err("invalid tape pointer"));
+        }
+
+        self.load(prog)?;
+        self.tape = tape;
+        self.tape_ptr = tape_ptr;
+        self.code_ptr = code_ptr;
+        self.steps = steps;
+        self.input = input;
+        Ok(())
+    }
+}
+
+fn write_uint(buf: &mut Vec<u8>, mut n: u64) {
+    loop {
+        let b = (n & 0x7F) as u8;
+        n >>= 7;
+        if n == 0 {
+            buf.push(b);
+            break;
+        }
+        buf.push(b | 0x80);
+    }
+}
+
+fn write_int(buf: &mut Vec<u8>, n: i64) {
+    write_uint(buf, ((n << 1) ^ (n >> 63)) as u64);
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Some(head)
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        self.bytes(1).map(|b| b[0])
+    }
+
+    fn uint(&mut self) -> Option<u64> {
+        let mut n = 0u64;
+        for shift in (0..64).step_by(7) {
+            let b = self.byte()?;
+            n |= ((b & 0x7F) as u64) << shift;
+            if b & 0x80 == 0 {
+                return Some(n);
+            }
+        }
+        None
+    }
+
+    fn int(&mut self) -> Option<i64> {
+        let n = self.uint()?;
+        Some((n >> 1) as i64 ^ -((n & 1) as i64))
+    }
+}
@@ -0,0 +1,81 @@
+use std::fmt;
+
+// A position in program source. Lines and columns start at 1, columns count characters rather than bytes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SourceLoc {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl SourceLoc {
+    pub fn locate(code: &str, offset: usize) -> Self {
+        let line_start = code[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        Self {
+            offset,
+            line: code[..offset].matches('\n').count() + 1,
+            column: code[line_start..offset].chars().count() + 1,
+        }
+    }
+
+    // The source line containing the location with a caret under its column, indented to go below a message
+    pub fn excerpt(&self, code: &str) -> String {
+        format!("  {}\n  {}^", line_at(code, self.offset), " ".repeat(self.column - 1))
+    }
+}
+
+impl fmt::Display for SourceLoc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {} (offset {})", self.line, self.column, self.offset)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Unmatched {
+    pub bracket: char,
+    pub loc: SourceLoc,
+    // The source line containing the bracket, without its line ending
+    pub source_line: String,
+}
+
+impl Unmatched {
+    // `bracket` is the command, which may be written differently in the source at `offset`
+    pub(crate) fn new(code: &str, offset: usize, bracket: char) -> Self {
+        Self {
+            bracket,
+            loc: SourceLoc::locate(code, offset),
+            source_line: line_at(code, offset).to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Unmatched {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "unmatched '{}' at {}", self.bracket, self.loc)?;
+        writeln!(f, "  {}", self.source_line)?;
+        write!(f, "  {}^", " ".repeat(self.loc.column - 1))
+    }
+}
+
+// The source line containing a position, without its line ending
+fn line_at(code: &str, offset: usize) -> &str {
+    let line_start = code[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line_end = code[offset..].find('\n').map(|i| offset + i).unwrap_or(code.len());
+    code[line_start..line_end].trim_end_matches('\r')
+}
+
+// Find every unmatched bracket in the source, in order of position
+pub fn unmatched_brackets(code: &str) -> Vec<Unmatched> {
+    let mut open = Vec::new();
+    let mut unmatched = Vec::new();
+    for (pos, c) in code.bytes().enumerate() {
+        match c {
+            b'[' => open.push(pos),
+            b']' if open.pop().is_none() => unmatched.push(pos),
+            _ => {},
+        }
+    }
+    unmatched.extend(open);
+    unmatched.sort_unstable();
+    unmatched.into_iter().map(|pos| Unmatched::new(code, pos, code.as_bytes()[pos] as char)).collect()
+}
@@ -0,0 +1,91 @@
+use super::{
+    Cell,
+    TapeMode,
+};
+
+// Cells are addressed by logical position. `origin` is the physical index of logical cell 0, which only moves when an
+// infinite tape grows to the left.
+#[derive(Clone, Debug)]
+pub struct Tape<C: Cell = u8> {
+    cells: Vec<C>,
+    origin: usize,
+    mode: TapeMode,
+}
+
+impl<C: Cell> Tape<C> {
+    pub fn new(len: usize, mode: TapeMode) -> Self {
+        Self {
+            cells: vec![C::default(); len],
+            origin: 0,
+            mode,
+        }
+    }
+
+    pub(crate) fn from_parts(cells: Vec<C>, origin: usize, mode: TapeMode) -> Self {
+        Self {
+            cells,
+            origin,
+            mode,
+        }
+    }
+
+    pub fn mode(&self) -> TapeMode {
+        self.mode
+    }
+
+    // The range of logical positions currently backed by memory
+    pub fn start(&self) -> isize {
+        -(self.origin as isize)
+    }
+
+    pub fn end(&self) -> isize {
+        (self.cells.len() - self.origin) as isize
+    }
+
+    pub fn cells(&self) -> &[C] {
+        &self.cells
+    }
+
+    #[cfg(all(feature = "jit", target_arch = "x86_64", target_os = "linux"))]
+    pub(crate) fn cells_mut(&mut self) -> &mut [C] {
+        &mut self.cells
+    }
+
+    pub fn get(&self, pos: isize) -> C {
+        self.index(pos)
+            .map(|idx| self.cells[idx])
+            .unwrap_or_default()
+    }
+
+    // Returns `None` if the position lies outside a fixed tape
+    pub fn get_mut(&mut self, pos: isize) -> Option<&mut C> {
+        self.reserve(pos);
+        let idx = self.index(pos)?;
+        Some(&mut self.cells[idx])
+    }
+
+    fn index(&self, pos: isize) -> Option<usize> {
+        let idx = pos.checked_add(self.origin as isize)?;
+        if idx >= 0 && (idx as usize) < self.cells.len() {
+            Some(idx as usize)
+        } else {
+            None
+        }
+    }
+
+    fn reserve(&mut self, pos: isize) {
+        match self.mode {
+            TapeMode::Fixed => {},
+            _ if pos >= self.end() => {
+                let len = (pos + self.origin as isize + 1) as usize;
+                self.cells.resize(len.max(self.cells.len() * 2), C::default());
+            },
+            TapeMode::Infinite if pos < self.start() => {
+                let extra = ((self.start() - pos) as usize).max(self.cells.len());
+                self.cells.splice(0..0, vec![C::default(); extra]);
+                self.origin += extra;
+            },
+            _ => {},
+        }
+    }
+}
@@ -0,0 +1,149 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::{
+    cell::RefCell,
+    fs,
+    path::PathBuf,
+    process::Command,
+    rc::Rc,
+};
+use fuckvm::{
+    backend::c,
+    vm::{
+        Cell,
+        EofPolicy,
+        Overflow,
+        Program,
+        Tape,
+        Vm,
+        VmConfig,
+    },
+};
+use common::{
+    HELLO_WORLD,
+    HIR_INPUTS,
+    Outcome,
+    POINTER_ERRORS,
+};
+
+// The tests are skipped when there's no C compiler
+fn have_cc() -> bool {
+    let found = Command::new("cc").arg("--version").output().is_ok_and(|out| out.status.success());
+    if !found {
+        eprintln!("cc not found, skipping");
+    }
+    found
+}
+
+// Compile the C source to `name` in the test's temporary directory and run it
+fn run_c(name: &str, source: &str, input: &[u8]) -> Outcome {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("c");
+    fs::create_dir_all(&dir).unwrap();
+    let (src, exe) = (dir.join(format!("{}.c", name)), dir.join(name));
+    fs::write(&src, source).unwrap();
+
+    let cc = Command::new("cc")
+        .args(["-std=c99", "-Wall", "-Wextra", "-pedantic", "-Werror", "-o"])
+        .arg(&exe)
+        .arg(&src)
+        .output()
+        .unwrap();
+    assert!(cc.status.success(), "cc failed:\n{}\n{}", String::from_utf8_lossy(&cc.stderr), source);
+    common::run_native(&exe, input)
+}
+
+// Returns false if the interpreter doesn't halt within 100,000 steps, in which case nothing is compiled
+fn check<C: Cell>(name: &str, code: &str, config: &VmConfig, input: &[u8]) -> bool {
+    common::check_native::<C>(code, config, input, |prog| run_c(name, &c::emit::<C>(prog, config).unwrap(), input))
+}
+
+#[test]
+fn hello_world() {
+    if !have_cc() {
+        return;
+    }
+    assert!(check::<u8>("hello_world", HELLO_WORLD, &VmConfig::default(), b""));
+    let source = c::transpile::<u8>(HELLO_WORLD, &VmConfig::default()).unwrap();
+    assert_eq!(run_c("hello_world", &source, b"").stdout, b"Hello World!\n");
+}
+
+#[test]
+fn compiled_hir() {
+    if !have_cc() {
+        return;
+    }
+    let bf = common::compiled_hir();
+    for input in HIR_INPUTS {
+        assert!(check::<u8>("compiled_hir", &bf, &VmConfig::default(), input));
+    }
+}
+
+#[test]
+fn errors_and_eof() {
+    if !have_cc() {
+        return;
+    }
+    let config = VmConfig::default().with_tape_len(8);
+    for code in POINTER_ERRORS {
+        assert!(check::<u8>("errors", code, &config, b""));
+    }
+    for eof in [EofPolicy::Zero, EofPolicy::MinusOne, EofPolicy::Unchanged, EofPolicy::Error] {
+        assert!(check::<u16>("eof", "+++,.,.,.", &config.clone().with_eof(eof), b"ab"));
+    }
+    let trapping = config.clone().with_overflow(Overflow::Trapping);
+    assert!(check::<u8>("overflow", "+>-", &trapping, b""));
+    assert!(check::<u8>("overflow", &"+".repeat(256), &trapping, b""));
+}
+
+#[test]
+fn debug_dump() {
+    if !have_cc() {
+        return;
+    }
+    let config = VmConfig::default().with_tape_len(32).with_debug_chars(&['#']);
+    let code = "++#>+++[-<+>]#<#";
+    let prog = Program::compile_for(code, &config).unwrap();
+
+    // Format the dump the way the C program does
+    let dump = Rc::new(RefCell::new(String::new()));
+    let mut vm: Vm = Vm::with_config(config.clone());
+    let hook_dump = dump.clone();
+    vm.set_debug_hook(move |tape: &Tape, ptr: isize, pos: usize| {
+        let cells = (0..20).map(|i| tape.get(i).to_string()).collect::<Vec<_>>();
+        *hook_dump.borrow_mut() += &format!("[{}] ptr={}: {}\n", pos, ptr, cells.join(", "));
+    });
+    vm.run_with(&prog, &mut &b""[..], &mut Vec::new()).unwrap();
+
+    let outcome = run_c("debug_dump", &c::emit::<u8>(&prog, &config).unwrap(), b"");
+    assert_eq!(outcome.stderr, *dump.borrow());
+}
+
+#[test]
+fn unsupported_config() {
+    let prog = Program::compile("+.").unwrap();
+    for config in common::unsupported_configs() {
+        assert!(c::emit::<u8>(&prog, &config).is_err());
+    }
+}
+
+#[test]
+fn differential() {
+    if !have_cc() {
+        return;
+    }
+    let overflows = [Overflow::Wrapping, Overflow::Saturating, Overflow::Trapping];
+    let mut compared = 0;
+    for (i, case) in common::random_cases(0xd1b54a32d192ed03, 150).enumerate() {
+        let config = case.config.with_overflow(overflows[i % 3]);
+        let name = format!("differential_{}", i);
+        let ran = match i % 4 {
+            0 | 1 => check::<u8>(&name, &case.code, &config, &case.input),
+            2 => check::<u16>(&name, &case.code, &config, &case.input),
+            _ => check::<u32>(&name, &case.code, &config, &case.input),
+        };
+        compared += ran as usize;
+    }
+    assert!(compared > 75);
+}
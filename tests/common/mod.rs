@@ -0,0 +1,209 @@
+// Fixtures shared by the backend tests, which each use a different subset
+#![allow(dead_code)]
+
+use std::{
+    io::Write,
+    path::Path,
+    process::{
+        Command,
+        Stdio,
+    },
+    thread,
+    time::Duration,
+};
+use fuckvm::{
+    bf::bfir,
+    ir::{
+        Type,
+        Value,
+        hir,
+    },
+    vm::{
+        Cell,
+        EofPolicy,
+        ExecStatus,
+        Program,
+        TapeMode,
+        Vm,
+        VmConfig,
+        VmError,
+    },
+    Error,
+};
+
+pub const HELLO_WORLD: &str = r#"++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++."#;
+
+// Programs that move the pointer off either end of an 8-cell tape, by single moves, scans and multiply loops
+pub const POINTER_ERRORS: [&str; 5] = ["+>+<<", "+[>+]", "+[<<]", ">>>>>>>+[-<<<<<<<+>>>>>>>>]", ">>>>>>>+[->+<]"];
+
+// Inputs for `compiled_hir` covering both branches and running out of input
+pub const HIR_INPUTS: [&[u8]; 3] = [b"55", b"57", b""];
+
+// A small HIR program that compares two input bytes and then loops a few times, lowered to bfir
+pub fn hir_program() -> bfir::Program {
+    let hir = hir::Program::new()
+        .with_function("main", hir::Function::new(Type::Empty, ("in", Type::Empty))
+            .with_block("entry", hir::Block::new(hir::Branch::Goto("loop".into()))
+                .with_op(hir::Op::byte_in("a"))
+                .with_op(hir::Op::byte_in("b"))
+                .with_op(hir::Op::byte_eq("eq", "a", "b"))
+                .with_op(hir::Op::byte_decl("zero", Value::Byte(b'0')))
+                .with_op(hir::Op::byte_add("eq_l", "eq", "zero"))
+                .with_op(hir::Op::byte_out("eq_l"))
+                .with_op(hir::Op::byte_decl("count", Value::Byte(3)))
+            )
+            .with_block("loop", hir::Block::new(hir::Branch::if_not_zero("count", "loop", "exit"))
+                .with_op(hir::Op::byte_decl("x", Value::Byte(b'x')))
+                .with_op(hir::Op::byte_out("x"))
+                .with_op(hir::Op::byte_decr("count"))
+            )
+            .with_block("exit", hir::Block::new(hir::Branch::Exit))
+        );
+    bfir::Program::from_lir(&hir.to_lir().unwrap())
+}
+
+pub fn compiled_hir() -> String {
+    hir_program().to_bf()
+}
+
+// Configs that no backend supports
+pub fn unsupported_configs() -> Vec<VmConfig> {
+    vec![
+        VmConfig::default().with_tape_len(0),
+        VmConfig::default().with_lenient_pointer(true),
+        VmConfig::default().with_tape_mode(TapeMode::Growable),
+        VmConfig::default().with_tape_mode(TapeMode::Infinite),
+    ]
+}
+
+pub struct Run<C: Cell> {
+    pub vm: Vm<C>,
+    pub output: Vec<u8>,
+    pub result: Result<(), Error>,
+}
+
+// Run a program on the interpreter, returning `None` if it doesn't halt within the fuel limit
+pub fn interpret<C: Cell>(prog: &Program, config: &VmConfig, mut input: &[u8], fuel: u64) -> Option<Run<C>> {
+    let mut vm = Vm::with_config(config.clone());
+    let mut output = Vec::new();
+    vm.load(prog).unwrap();
+    let result = match vm.resume_with(fuel, &mut input, &mut output) {
+        Ok(ExecStatus::Halted) => Ok(()),
+        Ok(_) => return None,
+        Err(err) => Err(err),
+    };
+    Some(Run { vm, output, result })
+}
+
+// What a compiled program prints on stderr for an error, which is the `VmError` message without the step count
+pub fn native_message(err: &Error) -> String {
+    match err {
+        Error::VmError(VmError::PointerUnderflow { code_pos, .. }) =>
+            format!("tape pointer moved below the start of the tape at offset {}", code_pos),
+        Error::VmError(VmError::PointerOverflow { code_pos, .. }) =>
+            format!("tape pointer moved past the end of the tape at offset {}", code_pos),
+        err => err.to_string(),
+    }
+}
+
+const ETXTBSY: i32 = 26;
+
+// How a compiled program exited
+#[derive(Debug, PartialEq)]
+pub struct Outcome {
+    pub stdout: Vec<u8>,
+    pub stderr: String,
+    pub status: i32,
+}
+
+// Run a compiled program, feeding it the given input
+pub fn run_native(path: &Path, input: &[u8]) -> Outcome {
+    // Another test thread forking while the file was open for writing can briefly leave it busy
+    let mut child = loop {
+        match Command::new(path).stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn() {
+            Err(err) if err.raw_os_error() == Some(ETXTBSY) => thread::sleep(Duration::from_millis(10)),
+            child => break child.unwrap(),
+        }
+    };
+    // The program may exit without reading all of its input
+    let _ = child.stdin.take().unwrap().write_all(input);
+    let out = child.wait_with_output().unwrap();
+    Outcome {
+        stdout: out.stdout,
+        stderr: String::from_utf8(out.stderr).unwrap(),
+        status: out.status.code().unwrap(),
+    }
+}
+
+// Compare the outcome of a program compiled and run by `run` with the interpreter. Returns false if the interpreter
+// doesn't halt within 100,000 steps, in which case `run` isn't called.
+pub fn check_native<C: Cell>(
+    code: &str,
+    config: &VmConfig,
+    input: &[u8],
+    run: impl FnOnce(&Program) -> Outcome,
+) -> bool {
+    let prog = Program::compile_for(code, config).unwrap();
+    let Some(interpreted) = interpret::<C>(&prog, config, input, 100_000) else {
+        return false;
+    };
+    let result = interpreted.result.as_ref().err();
+    let expected = Outcome {
+        stdout: interpreted.output,
+        stderr: result.map_or(String::new(), |err| format!("{}\n", native_message(err))),
+        status: result.is_some() as i32,
+    };
+    assert_eq!(run(&prog), expected, "program: {}", code);
+    true
+}
+
+// A small deterministic generator, so failures are reproducible
+pub struct Rng(pub u64);
+
+impl Rng {
+    pub fn next(&mut self, n: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % n
+    }
+}
+
+pub fn random_program(rng: &mut Rng, depth: usize) -> String {
+    let mut code = String::new();
+    for _ in 0..rng.next(12) + 1 {
+        match rng.next(15) {
+            0..=2 => code.push('+'),
+            3..=4 => code.push('-'),
+            5..=6 => code.push('>'),
+            7 => code.push('<'),
+            8 => code.push('.'),
+            9 => code.push(','),
+            10 => code += "[-]",
+            11 => code += "[->+++>+<<]",
+            12 if depth < 3 => code += &format!("[{}]", random_program(rng, depth + 1)),
+            13 => code += "--------",
+            _ => code += if rng.next(2) == 0 { "[>]" } else { "[<<]" },
+        }
+    }
+    code
+}
+
+pub struct Case {
+    pub code: String,
+    pub config: VmConfig,
+    pub input: Vec<u8>,
+}
+
+// Random programs with small tapes, a few bytes of input and every EOF policy
+pub fn random_cases(seed: u64, n: usize) -> impl Iterator<Item=Case> {
+    let mut rng = Rng(seed);
+    (0..n).map(move |i| {
+        let code = random_program(&mut rng, 0);
+        let config = VmConfig::default()
+            .with_tape_len(rng.next(24) as usize + 1)
+            .with_eof([EofPolicy::Zero, EofPolicy::MinusOne, EofPolicy::Unchanged, EofPolicy::Error][i % 4]);
+        let input = (0..rng.next(6)).map(|_| rng.next(256) as u8).collect();
+        Case { code, config, input }
+    })
+}
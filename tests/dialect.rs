@@ -0,0 +1,117 @@
+mod common;
+
+use fuckvm::{
+    vm::{
+        Dialect,
+        ExecStatus,
+        Program,
+        Vm,
+        VmConfig,
+        VmError,
+    },
+    Error,
+};
+use common::{
+    HELLO_WORLD,
+    HIR_INPUTS,
+};
+
+const WORDS: &str = "
+# One word per command
+> right
+< left
++ inc
+- dec
+. out
+, in
+[ loop
+] end
+";
+
+fn run(code: &str, config: VmConfig, mut input: &[u8]) -> Vec<u8> {
+    let mut vm: Vm = Vm::with_config(config);
+    let mut output = Vec::new();
+    let prog = Program::compile_for(code, vm.config()).unwrap();
+    vm.run_with(&prog, &mut input, &mut output).unwrap();
+    output
+}
+
+fn dialects() -> Vec<Dialect> {
+    vec![
+        Dialect::ook(),
+        Dialect::parse(WORDS).unwrap(),
+        Dialect::new(["→", "←", "⊕", "⊖", "out", "in", "loop{", "}"], "\n").unwrap(),
+    ]
+}
+
+fn commands(code: &str) -> String {
+    code.chars().filter(|c| "><+-.,[]".contains(*c)).collect()
+}
+
+// Emitting BF in a dialect and translating it back gives the same commands, with separators kept as comments
+#[test]
+fn round_trip() {
+    let bf = common::compiled_hir();
+    for dialect in dialects() {
+        assert_eq!(commands(&dialect.to_bf(&dialect.emit(&bf))), commands(&bf));
+    }
+}
+
+// Programs emitted in a dialect run the same on a VM configured for it
+#[test]
+fn emit_and_run() {
+    let expected = run(HELLO_WORLD, VmConfig::default(), b"");
+    for dialect in dialects() {
+        let code = dialect.emit(HELLO_WORLD);
+        assert_eq!(run(&code, VmConfig::default().with_dialect(dialect), b""), expected);
+    }
+
+    let prog = common::hir_program();
+    let bf = prog.to_bf();
+    for input in HIR_INPUTS {
+        let expected = run(&bf, VmConfig::default(), input);
+        for dialect in dialects() {
+            let code = prog.to_dialect(&dialect);
+            assert_eq!(run(&code, VmConfig::default().with_dialect(dialect), input), expected);
+        }
+    }
+}
+
+#[test]
+fn invalid_maps() {
+    assert!(Dialect::parse("+ inc\n- dec").is_err());
+    assert!(Dialect::parse(&format!("{}\nx nop", WORDS)).is_err());
+    assert!(Dialect::parse(&format!("{}\n+ add", WORDS)).is_err());
+    assert!(Dialect::parse(&WORDS.replace("dec", "inc")).is_err());
+}
+
+// Positions in compiled programs refer to the dialect's source rather than the BF it was translated to
+#[test]
+fn source_positions() {
+    let config = VmConfig::default().with_dialect(Dialect::ook());
+    let code = "Ook. Ook.\nOok! Ook? Ook. Ook.";
+    match Program::compile_for(code, &config) {
+        Err(VmError::UnmatchedBrackets(unmatched)) => {
+            assert_eq!(unmatched.len(), 1);
+            assert_eq!((unmatched[0].bracket, unmatched[0].loc.offset), ('[', 10));
+            assert_eq!((unmatched[0].loc.line, unmatched[0].loc.column), (2, 1));
+            assert_eq!(unmatched[0].source_line, "Ook! Ook? Ook. Ook.");
+        },
+        result => panic!("unexpected {:?}", result),
+    }
+
+    // A breakpoint anywhere in a token stops before its command
+    let prog = Program::compile_for("Ook. Ook. Ook. Ook. Ook! Ook.", &config).unwrap();
+    assert_eq!(prog.span(1), Some(20..29));
+    let mut vm: Vm = Vm::with_config(config.clone());
+    vm.load(&prog).unwrap();
+    vm.add_breakpoint(23);
+    assert_eq!(vm.advance(None).unwrap(), ExecStatus::Breakpoint(23));
+    assert_eq!(vm.code_pos(), Some(20));
+
+    let mut vm: Vm = Vm::with_config(config);
+    match vm.run_to_vec("Ook. Ook.\nOok? Ook.", b"") {
+        Err(Error::VmError(err)) => assert_eq!(err.code_pos(), Some(10)),
+        result => panic!("unexpected {:?}", result),
+    }
+}
@@ -0,0 +1,86 @@
+#![cfg(all(target_arch = "x86_64", target_os = "linux"))]
+
+mod common;
+
+use std::{
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::PathBuf,
+};
+use fuckvm::{
+    backend::elf,
+    vm::{
+        EofPolicy,
+        Overflow,
+        Program,
+        VmConfig,
+    },
+};
+use common::{
+    HELLO_WORLD,
+    HIR_INPUTS,
+    Outcome,
+    POINTER_ERRORS,
+};
+
+// Write the executable to `name` in the test's temporary directory and run it
+fn run_elf(name: &str, exe: &[u8], input: &[u8]) -> Outcome {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("elf");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    fs::write(&path, exe).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    common::run_native(&path, input)
+}
+
+// Returns false if the interpreter doesn't halt within 100,000 steps
+fn check(name: &str, code: &str, config: &VmConfig, input: &[u8]) -> bool {
+    common::check_native::<u8>(code, config, input, |prog| run_elf(name, &elf::emit(prog, config).unwrap(), input))
+}
+
+#[test]
+fn hello_world() {
+    assert!(check("hello_world", HELLO_WORLD, &VmConfig::default(), b""));
+    let exe = elf::assemble(HELLO_WORLD, &VmConfig::default()).unwrap();
+    assert_eq!(run_elf("hello_world", &exe, b"").stdout, b"Hello World!\n");
+}
+
+#[test]
+fn compiled_hir() {
+    let bf = common::compiled_hir();
+    for input in HIR_INPUTS {
+        assert!(check("compiled_hir", &bf, &VmConfig::default(), input));
+    }
+}
+
+#[test]
+fn errors_and_eof() {
+    let config = VmConfig::default().with_tape_len(8);
+    for code in POINTER_ERRORS {
+        assert!(check("errors", code, &config, b""));
+    }
+    for eof in [EofPolicy::Zero, EofPolicy::MinusOne, EofPolicy::Unchanged, EofPolicy::Error] {
+        let config = config.clone().with_eof(eof);
+        assert!(check("eof", "+++,.,.,.", &config, b"ab"));
+        assert!(check("eof", ",.,.,.,.,.", &config, b"echo"));
+    }
+}
+
+#[test]
+fn unsupported_config() {
+    let prog = Program::compile("+.").unwrap();
+    let mut configs = common::unsupported_configs();
+    configs.push(VmConfig::default().with_overflow(Overflow::Trapping));
+    for config in configs {
+        assert!(elf::emit(&prog, &config).is_err());
+    }
+}
+
+#[test]
+fn differential() {
+    let mut compared = 0;
+    for (i, case) in common::random_cases(0x94d049bb133111eb, 400).enumerate() {
+        compared += check(&format!("differential_{}", i), &case.code, &case.config, &case.input) as usize;
+    }
+    assert!(compared > 200);
+}
@@ -0,0 +1,141 @@
+#![cfg(all(feature = "jit", target_arch = "x86_64", target_os = "linux"))]
+
+mod common;
+
+use std::{
+    cell::RefCell,
+    rc::Rc,
+};
+use fuckvm::vm::{
+    EofPolicy,
+    JitProgram,
+    Program,
+    Tape,
+    Vm,
+    VmConfig,
+};
+use common::{
+    HELLO_WORLD,
+    HIR_INPUTS,
+    POINTER_ERRORS,
+};
+
+#[derive(Debug, PartialEq)]
+struct Outcome {
+    output: Vec<u8>,
+    result: Result<(), String>,
+    tape: Vec<u8>,
+    tape_ptr: isize,
+    steps: u64,
+    code_ptr: usize,
+}
+
+fn outcome(vm: &Vm, output: Vec<u8>, result: Result<(), String>) -> Outcome {
+    Outcome {
+        output,
+        result,
+        tape: vm.tape().cells().to_vec(),
+        tape_ptr: vm.tape_ptr(),
+        steps: vm.steps(),
+        code_ptr: vm.code_ptr(),
+    }
+}
+
+// Returns `None` if the interpreter doesn't finish within the fuel limit
+fn interpret(prog: &Program, config: &VmConfig, input: &[u8], fuel: u64) -> Option<Outcome> {
+    let run = common::interpret(prog, config, input, fuel)?;
+    Some(outcome(&run.vm, run.output, run.result.map_err(|err| err.to_string())))
+}
+
+fn jit(prog: &Program, config: &VmConfig, mut input: &[u8]) -> Outcome {
+    let jit = JitProgram::compile(prog).unwrap();
+    let mut vm: Vm = Vm::with_config(config.clone());
+    let mut output = Vec::new();
+    let result = vm.run_jit_with(&jit, &mut input, &mut output).map_err(|err| err.to_string());
+    outcome(&vm, output, result)
+}
+
+fn check(code: &str, config: &VmConfig, input: &[u8]) {
+    let prog = Program::compile(code).unwrap();
+    let expected = interpret(&prog, config, input, u64::MAX).unwrap();
+    assert_eq!(jit(&prog, config, input), expected, "program: {}", code);
+}
+
+#[test]
+fn hello_world() {
+    check(HELLO_WORLD, &VmConfig::default(), b"");
+    let mut vm = Vm::new();
+    let mut output = Vec::new();
+    vm.exec_jit_with(HELLO_WORLD, &mut &b""[..], &mut output).unwrap();
+    assert_eq!(output, b"Hello World!\n");
+}
+
+#[test]
+fn compiled_hir() {
+    let bf = common::compiled_hir();
+    for input in HIR_INPUTS {
+        check(&bf, &VmConfig::default(), input);
+    }
+}
+
+#[test]
+fn pointer_errors() {
+    let config = VmConfig::default().with_tape_len(8);
+    for code in POINTER_ERRORS {
+        check(code, &config, b"");
+    }
+}
+
+#[test]
+fn eof_policies() {
+    for eof in [EofPolicy::Zero, EofPolicy::MinusOne, EofPolicy::Unchanged, EofPolicy::Error] {
+        let config = VmConfig::default().with_eof(eof);
+        check("+++++,.,.+.", &config, b"a");
+        check(",.,.,.,.,.", &config, b"echo");
+    }
+}
+
+#[test]
+fn unsupported_config() {
+    let prog = Program::compile("+.").unwrap();
+    let jit = JitProgram::compile(&prog).unwrap();
+    for config in common::unsupported_configs() {
+        let mut vm: Vm = Vm::with_config(config);
+        assert!(vm.run_jit_with(&jit, &mut &b""[..], &mut Vec::new()).is_err());
+    }
+}
+
+#[test]
+fn debug_hook() {
+    let run = |use_jit: bool| {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let mut vm: Vm = Vm::with_config(VmConfig::default().with_debug_chars(&['#']));
+        let hook_calls = calls.clone();
+        vm.set_debug_hook(move |tape: &Tape, ptr: isize, pos: usize| {
+            hook_calls.borrow_mut().push((tape.get(ptr), ptr, pos));
+        });
+        let code = "++#>+++[-<+>]#<#";
+        let mut output = Vec::new();
+        if use_jit {
+            vm.exec_jit_with(code, &mut &b""[..], &mut output).unwrap();
+        } else {
+            vm.exec_with(code, &mut &b""[..], &mut output).unwrap();
+        }
+        let calls = calls.borrow().clone();
+        calls
+    };
+    assert_eq!(run(true), run(false));
+}
+
+#[test]
+fn differential() {
+    let mut compared = 0;
+    for case in common::random_cases(0x2545f4914f6cdd1d, 2000) {
+        let prog = Program::compile(&case.code).unwrap();
+        if let Some(expected) = interpret(&prog, &case.config, &case.input, 100_000) {
+            assert_eq!(jit(&prog, &case.config, &case.input), expected, "program: {}", case.code);
+            compared += 1;
+        }
+    }
+    assert!(compared > 1000);
+}
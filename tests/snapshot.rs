@@ -0,0 +1,147 @@
+mod common;
+
+use fuckvm::{
+    vm::{
+        ExecStatus,
+        Program,
+        TapeMode,
+        Vm,
+        VmConfig,
+        VmError,
+    },
+    Error,
+};
+use common::HELLO_WORLD;
+
+struct Run {
+    output: Vec<u8>,
+    tape: Vec<u8>,
+    tape_ptr: isize,
+    steps: u64,
+}
+
+fn finish(vm: &mut Vm, mut input: &[u8], mut output: Vec<u8>) -> Run {
+    assert_eq!(vm.resume_with(u64::MAX, &mut input, &mut output).unwrap(), ExecStatus::Halted);
+    Run {
+        output,
+        tape: vm.tape().cells().to_vec(),
+        tape_ptr: vm.tape_ptr(),
+        steps: vm.steps(),
+    }
+}
+
+// Stop after every possible number of steps, snapshot, and finish the run on a fresh VM
+fn check_round_trip(code: &str, config: VmConfig, input: &[u8]) {
+    let prog = Program::compile_for(code, &config).unwrap();
+    let mut vm: Vm = Vm::with_config(config.clone());
+    vm.load(&prog).unwrap();
+    let expected = finish(&mut vm, input, Vec::new());
+
+    for fuel in 0..=expected.steps {
+        let mut vm: Vm = Vm::with_config(config.clone());
+        let mut rest = input;
+        let mut output = Vec::new();
+        vm.load(&prog).unwrap();
+        vm.resume_with(fuel, &mut rest, &mut output).unwrap();
+        let snapshot = vm.snapshot();
+
+        let mut restored: Vm = Vm::with_config(config.clone());
+        restored.restore(&prog, &snapshot).unwrap();
+        assert_eq!(restored.snapshot(), snapshot);
+        let run = finish(&mut restored, rest, output);
+        assert_eq!(run.output, expected.output, "fuel {}", fuel);
+        assert_eq!(run.tape, expected.tape, "fuel {}", fuel);
+        assert_eq!(run.tape_ptr, expected.tape_ptr, "fuel {}", fuel);
+        assert_eq!(run.steps, expected.steps, "fuel {}", fuel);
+    }
+}
+
+#[test]
+fn round_trip() {
+    check_round_trip(&format!("{},[.,]", HELLO_WORLD), VmConfig::default(), b"abc");
+    check_round_trip("<<<+++[>++<-]>[>+<-]>.", VmConfig::default().with_tape_mode(TapeMode::Infinite), b"");
+    check_round_trip(">>>>>+++[<+>-]<.", VmConfig::default().with_tape_len(2).with_tape_mode(TapeMode::Growable), b"");
+}
+
+fn write_uint(buf: &mut Vec<u8>, mut n: u64) {
+    loop {
+        let b = (n & 0x7F) as u8;
+        n >>= 7;
+        if n == 0 {
+            buf.push(b);
+            break;
+        }
+        buf.push(b | 0x80);
+    }
+}
+
+fn write_int(buf: &mut Vec<u8>, n: i64) {
+    write_uint(buf, ((n << 1) ^ (n >> 63)) as u64);
+}
+
+// A snapshot of `prog` with the given pointers and tape, where `runs` are (zeros, literals) pairs followed by
+// `literals` values of 1
+fn snapshot(prog: &Program, tape_ptr: i64, start: i64, len: u64, runs: &[(u64, u64)]) -> Vec<u8> {
+    let mut buf = b"FVMS".to_vec();
+    buf.extend_from_slice(&[1, 1]);
+    write_uint(&mut buf, prog.fingerprint());
+    write_uint(&mut buf, 0);
+    write_int(&mut buf, tape_ptr);
+    write_uint(&mut buf, 0);
+    buf.push(0);
+    write_int(&mut buf, start);
+    write_uint(&mut buf, len);
+    for (zeros, literals) in runs {
+        write_uint(&mut buf, *zeros);
+        write_uint(&mut buf, *literals);
+        buf.extend(std::iter::repeat_n(1, (*literals).min(1000) as usize));
+    }
+    buf
+}
+
+fn is_invalid(result: Result<(), Error>) -> bool {
+    matches!(result, Err(Error::VmError(VmError::InvalidSnapshot(_))))
+}
+
+#[test]
+fn corrupted() {
+    let prog = Program::compile(HELLO_WORLD).unwrap();
+    let restore = |config: VmConfig, snapshot: &[u8]| Vm::<u8>::with_config(config).restore(&prog, snapshot);
+    let infinite = VmConfig::default().with_tape_mode(TapeMode::Infinite);
+
+    assert!(restore(VmConfig::default().with_tape_len(4), &snapshot(&prog, 3, 0, 4, &[(4, 0)])).is_ok());
+    assert!(restore(infinite.clone(), &snapshot(&prog, -5, -8, 8, &[(8, 0)])).is_ok());
+
+    let invalid = [
+        (infinite.clone(), snapshot(&prog, 0, i64::MIN, 8, &[(8, 0)])),
+        (infinite.clone(), snapshot(&prog, 0, 0, u64::MAX >> 1, &[(u64::MAX >> 1, 0)])),
+        (infinite.clone(), snapshot(&prog, 0, 0, 8, &[(4, 0), (u64::MAX, u64::MAX)])),
+        (infinite.clone(), snapshot(&prog, 0, 0, 8, &[(4, u64::MAX)])),
+        (infinite.clone(), snapshot(&prog, 0, 1, 8, &[(8, 0)])),
+        (infinite.clone(), snapshot(&prog, 0, -9, 8, &[(8, 0)])),
+        (VmConfig::default().with_tape_len(4), snapshot(&prog, 4, 0, 4, &[(4, 0)])),
+        (VmConfig::default().with_tape_len(4), snapshot(&prog, -1, 0, 4, &[(4, 0)])),
+        (VmConfig::default().with_tape_len(4), snapshot(&prog, 0, 0, 5, &[(5, 0)])),
+        (VmConfig::default().with_tape_mode(TapeMode::Growable), snapshot(&prog, -1, 0, 8, &[(8, 0)])),
+    ];
+    for (i, (config, snapshot)) in invalid.iter().enumerate() {
+        assert!(is_invalid(restore(config.clone(), snapshot)), "case {}", i);
+    }
+
+    // Take a real snapshot part way through, then damage it in every way we can think of
+    let mut vm: Vm = Vm::with_config(infinite.clone());
+    vm.load(&prog).unwrap();
+    vm.resume_with(200, &mut &b""[..], &mut Vec::new()).unwrap();
+    let good = vm.snapshot();
+    for len in 0..good.len() {
+        assert!(is_invalid(restore(infinite.clone(), &good[..len])), "truncated to {}", len);
+    }
+    for i in 0..good.len() {
+        for b in [0x00, 0x01, 0x7F, 0x80, 0xFF] {
+            let mut bad = good.clone();
+            bad[i] = b;
+            // Some of these are still valid snapshots, they just mustn't panic
+            let _ = restore(infinite.clone(), &bad);
+        }
+    }
+}
@@ -0,0 +1,306 @@
+mod common;
+
+use fuckvm::{
+    vm::{
+        Batch,
+        Case,
+        EofPolicy,
+        ExecStatus,
+        Outcome,
+        Overflow,
+        Program,
+        Recording,
+        SourceLoc,
+        TapeMode,
+        Vm,
+        VmConfig,
+        VmError,
+        WatchKind,
+    },
+    Error,
+};
+use common::HELLO_WORLD;
+
+// Multiply loops are only folded for wrapping cells and strict pointer checks, so a program compiled for those can't
+// be run by other VMs
+#[test]
+fn incompatible_program() {
+    let code = "+++[->++<]";
+    let prog = Program::compile(code).unwrap();
+    let configs = [
+        VmConfig::default().with_overflow(Overflow::Saturating),
+        VmConfig::default().with_overflow(Overflow::Trapping),
+        VmConfig::default().with_lenient_pointer(true),
+    ];
+    for config in configs {
+        let mut vm: Vm = Vm::with_config(config.clone());
+        assert!(matches!(vm.run(&prog), Err(Error::VmError(VmError::IncompatibleProgram))));
+        vm.run(&Program::compile_for(code, &config).unwrap()).unwrap();
+        assert_eq!((vm.get(0), vm.get(1)), (0, 6));
+    }
+}
+
+#[test]
+fn trapping_loop() {
+    let config = VmConfig::default().with_overflow(Overflow::Trapping);
+    let code = "+".repeat(255) + "[+--]";
+    let mut vm: Vm = Vm::with_config(config.clone());
+    let result = vm.run(&Program::compile_for(&code, &config).unwrap());
+    assert!(matches!(result, Err(Error::VmError(VmError::CellOverflow { ptr: 0, code_pos: 256 }))));
+}
+
+// Fixed tapes have no cells past the end, so even an empty tape can't be written silently
+#[test]
+fn empty_tape() {
+    let prog = Program::compile("+++").unwrap();
+    let config = VmConfig::default().with_tape_len(0);
+    let mut vm: Vm = Vm::with_config(config.clone());
+    let result = vm.run(&prog);
+    assert!(matches!(result, Err(Error::VmError(VmError::PointerOverflow { code_pos: 0, step: 1 }))));
+
+    let mut vm: Vm = Vm::with_config(config.clone().with_lenient_pointer(true));
+    vm.run(&Program::compile_for("+++", vm.config()).unwrap()).unwrap();
+    assert_eq!(vm.get(0), 0);
+
+    let mut vm: Vm = Vm::with_config(config.with_tape_mode(TapeMode::Growable));
+    vm.run(&prog).unwrap();
+    assert_eq!(vm.get(0), 3);
+}
+
+// Running out of fuel suspends the program, and resuming continues exactly where it stopped
+#[test]
+fn fuel_and_resume() {
+    let mut vm = Vm::new();
+    assert_eq!(vm.run_with_fuel(&Program::compile("+[]").unwrap(), 1000).unwrap(), ExecStatus::Suspended);
+    assert_eq!((vm.steps(), vm.code_ptr(), vm.get(0)), (1000, 2, 1));
+    assert_eq!(vm.resume(500).unwrap(), ExecStatus::Suspended);
+    assert_eq!(vm.steps(), 1500);
+
+    let prog = Program::compile(HELLO_WORLD).unwrap();
+    let mut vm = Vm::new();
+    let expected = vm.run_to_vec(HELLO_WORLD, b"").unwrap();
+    let steps = vm.steps();
+
+    let mut vm = Vm::new();
+    let mut output = Vec::new();
+    vm.load(&prog).unwrap();
+    while vm.resume_with(7, &mut &b""[..], &mut output).unwrap() == ExecStatus::Suspended {}
+    assert!(vm.is_halted());
+    assert_eq!((output, vm.steps()), (expected, steps));
+    assert_eq!(vm.resume(10).unwrap(), ExecStatus::Halted);
+}
+
+// The host drives the program through events, without the VM ever reading or writing on its own
+#[test]
+fn events() {
+    let mut vm = Vm::new();
+    vm.load(&Program::compile(",[+.,]").unwrap()).unwrap();
+    assert_eq!(vm.advance(None).unwrap(), ExecStatus::NeedsInput);
+    // Asking again without providing input doesn't execute anything
+    assert_eq!(vm.advance(None).unwrap(), ExecStatus::NeedsInput);
+    assert_eq!(vm.steps(), 0);
+
+    let mut input = b"abc".iter();
+    let mut output = Vec::new();
+    loop {
+        match vm.advance(None).unwrap() {
+            ExecStatus::NeedsInput => match input.next() {
+                Some(&b) => vm.provide_input(b),
+                None => vm.provide_eof(),
+            },
+            ExecStatus::Output(b) => output.push(b),
+            ExecStatus::Halted => break,
+            status => panic!("unexpected {:?}", status),
+        }
+    }
+    assert_eq!(output, b"bcd");
+
+    // Fuel still applies between events
+    let mut vm = Vm::new();
+    vm.load(&Program::compile("+++.").unwrap()).unwrap();
+    assert_eq!(vm.advance(Some(1)).unwrap(), ExecStatus::Suspended);
+    assert_eq!(vm.advance(Some(1)).unwrap(), ExecStatus::Output(3));
+    assert_eq!(vm.advance(Some(1)).unwrap(), ExecStatus::Halted);
+}
+
+#[test]
+fn breakpoints_and_stepping() {
+    let code = "++[>+[-]<-]>.";
+    let prog = Program::compile(code).unwrap();
+    let mut vm = Vm::new();
+    vm.load(&prog).unwrap();
+
+    // Breakpoints stop before the op at their position, and continuing doesn't hit the same one again
+    vm.add_breakpoint(3);
+    assert_eq!(vm.advance(None).unwrap(), ExecStatus::Breakpoint(3));
+    assert_eq!((vm.code_pos(), vm.tape_ptr(), vm.get(0)), (Some(3), 0, 2));
+    assert_eq!(vm.advance(None).unwrap(), ExecStatus::Breakpoint(3));
+    assert_eq!(vm.get(0), 1);
+
+    // Single steps ignore breakpoints
+    assert_eq!(vm.single_step().unwrap(), ExecStatus::Suspended);
+    assert_eq!((vm.code_pos(), vm.tape_ptr()), (Some(4), 1));
+    assert_eq!(vm.single_step().unwrap(), ExecStatus::Suspended);
+    assert_eq!(vm.code_pos(), Some(5));
+
+    // `[-]` is a single op, finishing the outer loop stops after its `]`
+    vm.remove_breakpoint(3);
+    assert_eq!(vm.step_over_loop().unwrap(), ExecStatus::Suspended);
+    assert_eq!((vm.code_pos(), vm.get(1)), (Some(8), 0));
+    assert_eq!(vm.run_to_loop_exit().unwrap(), ExecStatus::Paused);
+    assert_eq!((vm.code_pos(), vm.get(0)), (Some(11), 0));
+    assert_eq!(vm.advance(None).unwrap(), ExecStatus::Output(0));
+    assert_eq!(vm.advance(None).unwrap(), ExecStatus::Halted);
+
+    // Stepping over a loop runs it to completion
+    vm.load(&prog).unwrap();
+    assert_eq!(vm.single_step().unwrap(), ExecStatus::Suspended);
+    assert_eq!(vm.step_over_loop().unwrap(), ExecStatus::Paused);
+    assert_eq!((vm.code_pos(), vm.tape_ptr(), vm.get(0)), (Some(11), 0, 0));
+}
+
+// Stopping for a breakpoint abandons the step, so it can't pause the program later on
+#[test]
+fn interrupted_step() {
+    let prog = Program::compile("++[>+[-]<-]>.").unwrap();
+    let mut vm = Vm::new();
+    vm.load(&prog).unwrap();
+    assert_eq!(vm.single_step().unwrap(), ExecStatus::Suspended);
+    vm.add_breakpoint(3);
+    assert_eq!(vm.step_over_loop().unwrap(), ExecStatus::Breakpoint(3));
+    vm.clear_breakpoints();
+    assert_eq!(vm.advance(None).unwrap(), ExecStatus::Output(0));
+    assert_eq!(vm.advance(None).unwrap(), ExecStatus::Halted);
+
+    // Output doesn't interrupt it
+    let mut vm = Vm::new();
+    vm.load(&Program::compile("+[.-]+.").unwrap()).unwrap();
+    assert_eq!(vm.single_step().unwrap(), ExecStatus::Suspended);
+    assert_eq!(vm.step_over_loop().unwrap(), ExecStatus::Output(1));
+    assert_eq!(vm.advance(None).unwrap(), ExecStatus::Paused);
+    assert_eq!(vm.code_pos(), Some(5));
+}
+
+// Simplified loops are profiled with the iterations they replaced
+#[test]
+fn profile_simplified_loops() {
+    let code = "++++++++++[->+>+<<]>[-]>>+>+<<[>]";
+    let mut vm: Vm = Vm::with_config(VmConfig::default().with_profile(true));
+    vm.run(&Program::compile(code).unwrap()).unwrap();
+    let profile = vm.profile().unwrap();
+    let mut loops = profile.loops().into_iter().map(|l| (l.span, l.iters)).collect::<Vec<_>>();
+    loops.sort_by_key(|(span, _)| span.start);
+    assert_eq!(loops, [(10..19, 10), (20..23, 10), (30..33, 3)]);
+    assert!(profile.report(code, 10).contains("[->+>+<<]"));
+}
+
+#[test]
+fn record_and_replay() {
+    let prog = Program::compile(",[.,]").unwrap();
+    let mut vm = Vm::new();
+    let (mut output, mut log) = (Vec::new(), Vec::new());
+    let rec = vm.run_recorded(&prog, &mut &b"hi"[..], &mut output, &mut log).unwrap();
+    assert_eq!(rec.inputs, [(0, Some(b'h')), (3, Some(b'i')), (6, None)]);
+    assert_eq!((&rec.output[..], &output[..]), (&b"hi"[..], &b"hi"[..]));
+    assert_eq!(Recording::from_log(&String::from_utf8(log).unwrap()).unwrap(), rec);
+
+    let mut replayed = Vec::new();
+    vm.replay(&prog, &rec, &mut replayed).unwrap();
+    assert_eq!(replayed, b"hi");
+
+    // Any difference in behaviour is reported at the step where it happens
+    let diverged = |code: &str, rec: &Recording| {
+        match Vm::new().replay(&Program::compile(code).unwrap(), rec, &mut Vec::new()) {
+            Err(Error::VmError(VmError::ReplayDiverged { step, .. })) => step,
+            result => panic!("unexpected {:?}", result),
+        }
+    };
+    assert_eq!(diverged(",[+.,]", &rec), 4);
+    assert_eq!(diverged(",.,.", &rec), 2);
+    let truncated = Recording { inputs: rec.inputs[..2].to_vec(), output: rec.output.clone() };
+    assert_eq!(diverged(",[.,]", &truncated), 6);
+    assert!(matches!(Recording::from_log("in x 1"), Err(Error::VmError(VmError::InvalidRecording(_)))));
+
+    // A run that fails still logs the input it consumed
+    let mut vm: Vm = Vm::with_config(VmConfig::default().with_eof(EofPolicy::Error));
+    let mut log = Vec::new();
+    assert!(vm.run_recorded(&Program::compile(",,").unwrap(), &mut &b"a"[..], &mut Vec::new(), &mut log).is_err());
+    assert_eq!(log, b"in 0 97\neof 1\n");
+}
+
+// Runtime errors carry the source position of the op that failed, which can be shown in context
+#[test]
+fn error_location() {
+    let code = "+++\n>>+[<<<]";
+    let err = match Vm::new().run_to_vec(code, b"") {
+        Err(Error::VmError(err)) => err,
+        result => panic!("unexpected {:?}", result),
+    };
+    let loc = SourceLoc::locate(code, err.code_pos().unwrap());
+    assert_eq!((loc.line, loc.column), (2, 4));
+    assert_eq!(loc.excerpt(code), "  >>+[<<<]\n     ^");
+}
+
+// Watchpoints see every iteration of simplified loops, without changing the step count
+#[test]
+fn watch_simplified_loops() {
+    let watch = |code: &str, cell: isize, kind: WatchKind| {
+        let mut vm = Vm::new();
+        vm.load(&Program::compile(code).unwrap()).unwrap();
+        vm.add_watchpoint(cell, kind);
+        let mut hits = Vec::new();
+        loop {
+            match vm.advance(None).unwrap() {
+                ExecStatus::Watchpoint(hit) => hits.push((hit.old, hit.new, vm.get(0), vm.get(1))),
+                ExecStatus::Halted => break,
+                status => panic!("unexpected {:?}", status),
+            }
+        }
+        let mut unwatched = Vm::new();
+        unwatched.run_to_vec(code, b"").unwrap();
+        assert_eq!(vm.steps(), unwatched.steps());
+        hits
+    };
+
+    assert_eq!(watch("+++++[-]", 0, WatchKind::WriteValue(3)), [(4, 3, 3, 0)]);
+    assert_eq!(watch("+++++[->+<]", 1, WatchKind::WriteValue(3)), [(2, 3, 2, 3)]);
+    assert_eq!(watch("+++[->++<]", 1, WatchKind::Write), [(0, 2, 2, 2), (2, 4, 1, 4), (4, 6, 0, 6)]);
+    assert_eq!(watch("+++[->++<]", 0, WatchKind::Read).len(), 4);
+    assert_eq!(watch("+>+>+<<[>]", 2, WatchKind::Read), [(1, 1, 1, 1)]);
+}
+
+#[test]
+fn batch() {
+    let case = |name: &str, code: &str, input: &[u8], expected: Option<&[u8]>| Case {
+        name: name.to_string(),
+        code: code.to_string(),
+        input: input.to_vec(),
+        expected: expected.map(<[u8]>::to_vec),
+    };
+    let report = Batch::new()
+        .with_threads(3)
+        .with_fuel(10_000)
+        .with_case(case("echo", ",[.,]", b"hi", Some(b"hi")))
+        .with_case(case("minus", "-.", b"", Some(b"\xfe")))
+        .with_case(case("spin", "+[]", b"", None))
+        .with_case(case("underflow", "<", b"", None))
+        .with_case(case("unchecked", HELLO_WORLD, b"", None))
+        .run();
+    assert!(!report.all_passed());
+
+    let outcomes = report.results.iter().map(|result| result.outcome.name()).collect::<Vec<_>>();
+    assert_eq!(outcomes, ["passed", "failed", "out_of_fuel", "error", "passed"]);
+    assert!(matches!(&report.results[1].outcome, Outcome::Failed(expected) if expected == b"\xfe"));
+    assert_eq!(report.results[2].steps, 10_000);
+
+    // Output is written as hex, so bytes that aren't valid UTF-8 survive
+    let mut json = Vec::new();
+    report.write_json_lines(&mut json).unwrap();
+    let lines = String::from_utf8(json).unwrap();
+    let lines = lines.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 5);
+    assert!(lines[0].contains(r#""output":"6869""#));
+    assert!(lines[1].contains(r#""outcome":"failed""#));
+    assert!(lines[1].contains(r#""output":"ff","expected":"fe""#));
+    assert!(lines[3].contains(r#""error":"tape pointer moved below the start of the tape"#));
+}
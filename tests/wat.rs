@@ -0,0 +1,399 @@
+mod common;
+
+use std::{
+    collections::HashMap,
+    mem,
+};
+use fuckvm::{
+    backend::wat,
+    vm::{
+        Cell,
+        EofPolicy,
+        Program,
+        VmConfig,
+        VmError,
+    },
+    Error,
+};
+use common::{
+    HELLO_WORLD,
+    HIR_INPUTS,
+    POINTER_ERRORS,
+};
+
+// A minimal WAT parser and interpreter, covering just enough to run the emitter's output
+
+#[derive(Clone, Debug, PartialEq)]
+enum Sexp {
+    Atom(String),
+    List(Vec<Sexp>),
+}
+
+fn parse(src: &str) -> Sexp {
+    let mut stack = vec![Vec::new()];
+    let mut chars = src.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => stack.push(Vec::new()),
+            ')' => {
+                let list = stack.pop().unwrap();
+                stack.last_mut().expect("unbalanced ')'").push(Sexp::List(list));
+            },
+            ';' if chars.peek() == Some(&';') => while chars.next().is_some_and(|c| c != '\n') {},
+            c if c.is_whitespace() => {},
+            c => {
+                let mut atom = c.to_string();
+                let quoted = c == '"';
+                while let Some(&c) = chars.peek() {
+                    if quoted && c == '"' {
+                        atom.push(chars.next().unwrap());
+                        break;
+                    } else if !quoted && (c.is_whitespace() || c == '(' || c == ')') {
+                        break;
+                    }
+                    atom.push(chars.next().unwrap());
+                }
+                stack.last_mut().unwrap().push(Sexp::Atom(atom));
+            },
+        }
+    }
+    assert_eq!(stack.len(), 1, "unbalanced '('");
+    let mut top = stack.pop().unwrap();
+    assert_eq!(top.len(), 1);
+    top.pop().unwrap()
+}
+
+impl Sexp {
+    fn items(&self) -> &[Sexp] {
+        match self {
+            Sexp::List(items) => items,
+            Sexp::Atom(atom) => panic!("expected a list, found {}", atom),
+        }
+    }
+
+    fn head(&self) -> Option<&str> {
+        match self {
+            Sexp::List(items) => match items.first() {
+                Some(Sexp::Atom(atom)) => Some(atom),
+                _ => None,
+            },
+            Sexp::Atom(_) => None,
+        }
+    }
+
+    fn atoms(&self) -> Vec<&str> {
+        self.items()
+            .iter()
+            .filter_map(|item| match item {
+                Sexp::Atom(atom) => Some(atom.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Find the child list with the given head and atoms, e.g. `(export "run")`
+    fn find(&self, head: &[&str]) -> Option<&Sexp> {
+        self.items().iter().find(|item| matches!(item, Sexp::List(_)) && item.atoms().starts_with(head))
+    }
+}
+
+struct Module {
+    pages: usize,
+    run: Vec<String>,
+}
+
+// Check the overall shape of the module and extract the body of `run`
+fn check_structure(module: &Sexp) -> Module {
+    assert_eq!(module.head(), Some("module"));
+
+    let imports = module
+        .items()
+        .iter()
+        .filter(|item| item.head() == Some("import"))
+        .map(|item| {
+            let func = item.find(&["func"]).expect("import isn't a function");
+            let sig = func.items()[2..].to_vec();
+            (item.atoms()[1..].join(" "), func.atoms()[1].to_string(), sig)
+        })
+        .collect::<Vec<_>>();
+    let result_i32 = Sexp::List(vec![Sexp::Atom("result".into()), Sexp::Atom("i32".into())]);
+    let param_i32 = Sexp::List(vec![Sexp::Atom("param".into()), Sexp::Atom("i32".into())]);
+    assert_eq!(imports, vec![
+        ("\"env\" \"read_byte\"".to_string(), "$read_byte".to_string(), vec![result_i32.clone()]),
+        ("\"env\" \"write_byte\"".to_string(), "$write_byte".to_string(), vec![param_i32]),
+    ]);
+
+    let memory = module.find(&["memory"]).expect("no memory");
+    assert!(memory.find(&["export", "\"memory\""]).is_some());
+    let pages = memory.atoms()[1].parse().unwrap();
+
+    let funcs = module.items().iter().filter(|item| item.head() == Some("func")).collect::<Vec<_>>();
+    let export = |name: &str| {
+        let name = format!("\"{}\"", name);
+        funcs
+            .iter()
+            .find(|func| func.find(&["export", &name]).is_some())
+            .unwrap_or_else(|| panic!("{} not exported", name))
+    };
+    assert!(export("error_pos").find(&["result", "i32"]).is_some());
+    let run = export("run");
+    assert!(run.find(&["result", "i32"]).is_some());
+    assert!(run.find(&["local", "$p", "i32"]).is_some());
+
+    Module {
+        pages,
+        run: run.atoms()[1..].iter().map(|s| s.to_string()).collect(),
+    }
+}
+
+#[derive(Debug)]
+enum Instr {
+    Const(i32),
+    LocalGet(String),
+    LocalSet(String),
+    LocalTee(String),
+    GlobalSet,
+    Binary(String),
+    Eqz,
+    Load(usize),
+    Store(usize),
+    Call(String),
+    Block,
+    Loop,
+    If,
+    Else,
+    End,
+    Br(usize),
+    BrIf(usize),
+    Return,
+    Nop,
+}
+
+fn decode(tokens: &[String]) -> Vec<Instr> {
+    let mut instrs = Vec::new();
+    let mut tokens = tokens.iter();
+    while let Some(token) = tokens.next() {
+        let mut arg = || tokens.next().unwrap().clone();
+        instrs.push(match token.as_str() {
+            "i32.const" => Instr::Const(arg().parse().unwrap()),
+            "local.get" => Instr::LocalGet(arg()),
+            "local.set" => Instr::LocalSet(arg()),
+            "local.tee" => Instr::LocalTee(arg()),
+            "global.set" => {
+                assert_eq!(arg(), "$error_pos");
+                Instr::GlobalSet
+            },
+            "i32.add" | "i32.sub" | "i32.mul" | "i32.and" | "i32.shl" | "i32.shr_u" | "i32.lt_s" | "i32.ge_u" =>
+                Instr::Binary(token.clone()),
+            "i32.eqz" => Instr::Eqz,
+            "i32.load8_u" => Instr::Load(1),
+            "i32.load16_u" => Instr::Load(2),
+            "i32.load" => Instr::Load(4),
+            "i32.store8" => Instr::Store(1),
+            "i32.store16" => Instr::Store(2),
+            "i32.store" => Instr::Store(4),
+            "call" => Instr::Call(arg()),
+            "block" => Instr::Block,
+            "loop" => Instr::Loop,
+            "if" => Instr::If,
+            "else" => Instr::Else,
+            "end" => Instr::End,
+            "br" => Instr::Br(arg().parse().unwrap()),
+            "br_if" => Instr::BrIf(arg().parse().unwrap()),
+            "return" => Instr::Return,
+            "nop" => Instr::Nop,
+            token => panic!("unknown instruction {}", token),
+        });
+    }
+    instrs
+}
+
+// Returns the status and error position, along with the program's output and memory
+fn execute(module: &Module, input: &[u8]) -> (i32, i32, Vec<u8>, Vec<u8>) {
+    let code = decode(&module.run);
+
+    // Match each structured instruction with its `else` and `end`
+    let mut ends = HashMap::new();
+    let mut elses = HashMap::new();
+    let mut open = Vec::new();
+    for (i, instr) in code.iter().enumerate() {
+        match instr {
+            Instr::Block | Instr::Loop | Instr::If => open.push(i),
+            Instr::Else => {
+                elses.insert(*open.last().unwrap(), i);
+            },
+            Instr::End => {
+                ends.insert(open.pop().expect("unbalanced end"), i);
+            },
+            _ => {},
+        }
+    }
+    assert!(open.is_empty(), "unterminated block");
+
+    let mut memory = vec![0u8; module.pages * 0x10000];
+    let mut locals = HashMap::new();
+    let mut error_pos = 0;
+    let mut stack: Vec<i32> = Vec::new();
+    let mut labels: Vec<usize> = Vec::new();
+    let mut input = input.iter();
+    let mut output = Vec::new();
+    let mut pc = 0;
+
+    let branch = |labels: &mut Vec<usize>, depth: usize| -> usize {
+        let start = labels[labels.len() - 1 - depth];
+        if let Instr::Loop = code[start] {
+            labels.truncate(labels.len() - depth);
+            start + 1
+        } else {
+            labels.truncate(labels.len() - 1 - depth);
+            ends[&start] + 1
+        }
+    };
+
+    while pc < code.len() {
+        let mut next = pc + 1;
+        match &code[pc] {
+            Instr::Const(n) => stack.push(*n),
+            Instr::LocalGet(name) => stack.push(*locals.get(name).unwrap_or(&0)),
+            Instr::LocalSet(name) => {
+                locals.insert(name.clone(), stack.pop().unwrap());
+            },
+            Instr::LocalTee(name) => {
+                locals.insert(name.clone(), *stack.last().unwrap());
+            },
+            Instr::GlobalSet => error_pos = stack.pop().unwrap(),
+            Instr::Binary(op) => {
+                let (b, a) = (stack.pop().unwrap(), stack.pop().unwrap());
+                stack.push(match op.as_str() {
+                    "i32.add" => a.wrapping_add(b),
+                    "i32.sub" => a.wrapping_sub(b),
+                    "i32.mul" => a.wrapping_mul(b),
+                    "i32.and" => a & b,
+                    "i32.shl" => a.wrapping_shl(b as u32),
+                    "i32.shr_u" => ((a as u32) >> (b as u32 & 31)) as i32,
+                    "i32.lt_s" => (a < b) as i32,
+                    _ => ((a as u32) >= (b as u32)) as i32,
+                });
+            },
+            Instr::Eqz => {
+                let a = stack.pop().unwrap();
+                stack.push((a == 0) as i32);
+            },
+            Instr::Load(width) => {
+                let addr = stack.pop().unwrap() as usize;
+                let mut bytes = [0; 4];
+                bytes[..*width].copy_from_slice(&memory[addr..addr + width]);
+                stack.push(i32::from_le_bytes(bytes));
+            },
+            Instr::Store(width) => {
+                let (val, addr) = (stack.pop().unwrap(), stack.pop().unwrap() as usize);
+                memory[addr..addr + width].copy_from_slice(&val.to_le_bytes()[..*width]);
+            },
+            Instr::Call(name) => match name.as_str() {
+                "$read_byte" => stack.push(input.next().map(|b| *b as i32).unwrap_or(-1)),
+                "$write_byte" => output.push(stack.pop().unwrap() as u8),
+                name => panic!("unknown function {}", name),
+            },
+            Instr::Block | Instr::Loop => labels.push(pc),
+            Instr::If => {
+                if stack.pop().unwrap() != 0 {
+                    labels.push(pc);
+                } else if let Some(els) = elses.get(&pc) {
+                    labels.push(pc);
+                    next = els + 1;
+                } else {
+                    next = ends[&pc] + 1;
+                }
+            },
+            Instr::Else => next = branch(&mut labels, 0),
+            Instr::End => {
+                labels.pop();
+            },
+            Instr::Br(depth) => next = branch(&mut labels, *depth),
+            Instr::BrIf(depth) => if stack.pop().unwrap() != 0 {
+                next = branch(&mut labels, *depth);
+            },
+            Instr::Return => return (stack.pop().unwrap(), error_pos, output, memory),
+            Instr::Nop => {},
+        }
+        pc = next;
+    }
+    assert!(labels.is_empty());
+    (stack.pop().unwrap(), error_pos, output, memory)
+}
+
+fn check<C: Cell>(code: &str, config: &VmConfig, input: &[u8]) -> bool {
+    let prog = Program::compile(code).unwrap();
+    let Some(run) = common::interpret::<C>(&prog, config, input, 100_000) else {
+        return false;
+    };
+    let expected = match run.result {
+        Ok(()) => (wat::STATUS_HALTED, 0),
+        Err(Error::VmError(VmError::PointerUnderflow { code_pos, .. })) => (wat::STATUS_UNDERFLOW, code_pos as i32),
+        Err(Error::VmError(VmError::PointerOverflow { code_pos, .. })) => (wat::STATUS_OVERFLOW, code_pos as i32),
+        Err(Error::VmError(VmError::UnexpectedEof { code_pos })) => (wat::STATUS_EOF, code_pos as i32),
+        Err(err) => panic!("unexpected error {}", err),
+    };
+
+    let module = check_structure(&parse(&wat::emit::<C>(&prog, config).unwrap()));
+    let (status, error_pos, output, memory) = execute(&module, input);
+    assert_eq!((status, error_pos), expected, "program: {}", code);
+    assert_eq!(output, run.output, "program: {}", code);
+
+    let width = mem::size_of::<C>();
+    let tape = memory[..config.tape_len * width]
+        .chunks(width)
+        .map(|cell| cell.iter().rev().fold(0i64, |acc, b| acc << 8 | *b as i64))
+        .collect::<Vec<_>>();
+    let vm_tape = run.vm.tape().cells().iter().map(|cell| cell.to_i64()).collect::<Vec<_>>();
+    assert_eq!(tape, vm_tape, "program: {}", code);
+    true
+}
+
+#[test]
+fn hello_world() {
+    assert!(check::<u8>(HELLO_WORLD, &VmConfig::default(), b""));
+    let module = check_structure(&parse(&wat::transpile::<u8>(HELLO_WORLD, &VmConfig::default()).unwrap()));
+    assert_eq!(execute(&module, b"").2, b"Hello World!\n");
+}
+
+#[test]
+fn compiled_hir() {
+    let bf = common::compiled_hir();
+    for input in HIR_INPUTS {
+        assert!(check::<u8>(&bf, &VmConfig::default(), input));
+    }
+}
+
+#[test]
+fn errors_and_eof() {
+    let config = VmConfig::default().with_tape_len(8);
+    for code in POINTER_ERRORS {
+        assert!(check::<u8>(code, &config, b""));
+    }
+    for eof in [EofPolicy::Zero, EofPolicy::MinusOne, EofPolicy::Unchanged, EofPolicy::Error] {
+        assert!(check::<u16>("+++,.,.,.", &config.clone().with_eof(eof), b"ab"));
+    }
+}
+
+#[test]
+fn unsupported_config() {
+    let prog = Program::compile("+.").unwrap();
+    for config in common::unsupported_configs() {
+        assert!(wat::emit::<u8>(&prog, &config).is_err());
+    }
+}
+
+#[test]
+fn differential() {
+    let mut compared = 0;
+    for (i, case) in common::random_cases(0x9e3779b97f4a7c15, 600).enumerate() {
+        let ran = match i % 3 {
+            0 => check::<u8>(&case.code, &case.config, &case.input),
+            1 => check::<u16>(&case.code, &case.config, &case.input),
+            _ => check::<u32>(&case.code, &case.config, &case.input),
+        };
+        compared += ran as usize;
+    }
+    assert!(compared > 300);
+}